@@ -0,0 +1,341 @@
+//! Async adapter for the functions, witch block while reading or setting the wallpapers.
+//!
+//! The backends execute external programs and D-Bus calls synchronously.
+//! This module does not replace them by async io, instead the blocking work is moved to the blocking thread pool of [tokio],
+//! so the async runtime is not stalled.
+//! Each call occupies one thread of the pool until the backend is finished.
+//! The functions must be called from inside a tokio runtime.
+//! Creating and modifying the [`WallpaperBuilder`] does not block, so the synchronous api can be used for it.
+//! The closures, witch select the wallpapers, are called immediately at the current task.
+//! So they do not need to be `Send` and should not block either.
+//!
+//! Dropping a future before it is finished cancels the [`CancelToken`](crate::CancelToken) of the builder.
+//! The running command is killed and no further commands are executed.
+//! Already changed screens keep their new wallpaper.
+//! Registered backends and the fallback crate of macOS and Windows can not be interrupted,
+//! so they keep running at the blocking thread pool until they are finished.
+//! If the token was passed by [`BuilderOptions::cancel_token()`], all other users of the token are cancelled too.
+//! ```no_run
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use more_wallpapers::{Mode, WallpaperBuilder};
+//!
+//! let mut builder = WallpaperBuilder::new_async().await?;
+//! for mut screen in builder.screens_mut() {
+//! 	screen.set("/usr/share/wallpapers/1.jpg", Mode::Crop);
+//! }
+//! builder.apply_async().await?;
+//! # Ok(())}
+//! ```
+
+use crate::{
+	get_environment, Background, BuilderOptions, CancelToken, Environment, MissingRequirement, Mode, Plan, Screen,
+	WallpaperBuilder, WallpaperError, WallpaperSnapshot,
+};
+use camino::{Utf8Path, Utf8PathBuf};
+use std::{convert::Infallible, error::Error, future::Future, panic};
+
+/// cancel the token, if the future is dropped before the blocking work is finished
+struct CancelOnDrop(Option<CancelToken>);
+
+impl Drop for CancelOnDrop {
+	fn drop(&mut self) {
+		if let Some(token) = self.0.take() {
+			token.cancel();
+		}
+	}
+}
+
+/// run `f` at the blocking thread pool and forward its panics
+async fn spawn_blocking<T, F>(cancel: CancelToken, f: F) -> T
+where
+	T: Send + 'static,
+	F: FnOnce() -> T + Send + 'static,
+{
+	let mut guard = CancelOnDrop(Some(cancel));
+	let result = tokio::task::spawn_blocking(f).await;
+	guard.0 = None;
+	match result {
+		Ok(value) => value,
+		Err(err) => panic::resume_unwind(err.into_panic()),
+	}
+}
+
+impl BuilderOptions {
+	///Async version of [`Self::build()`].
+	pub async fn build_async(self) -> Result<WallpaperBuilder, WallpaperError> {
+		spawn_blocking(self.cancel.clone(), || self.build()).await
+	}
+}
+
+impl WallpaperBuilder {
+	///Async version of [`Self::new()`].
+	pub async fn new_async() -> Result<Self, WallpaperError> {
+		Self::options().build_async().await
+	}
+
+	///Async version of [`Self::with_environment()`].
+	pub async fn with_environment_async(environment: Environment) -> Result<Self, WallpaperError> {
+		Self::options().environment(environment).build_async().await
+	}
+
+	///Async version of [`Self::set_wallpapers()`].
+	pub fn set_wallpapers_async<F, P>(self, mut f: F) -> impl Future<Output = Result<(), WallpaperError>>
+	where
+		P: AsRef<Utf8Path>,
+		F: FnMut(&Screen) -> (P, Mode),
+	{
+		Self::apply_selected(self.try_select_backgrounds_now(|screen| {
+			let (path, mode) = f(screen);
+			Ok::<_, Infallible>(Some((path.as_ref().to_owned(), mode)))
+		}))
+	}
+
+	///Async version of [`Self::try_set_wallpapers()`].
+	pub fn try_set_wallpapers_async<F, P, E>(self, mut f: F) -> impl Future<Output = Result<(), WallpaperError>>
+	where
+		P: AsRef<Utf8Path>,
+		F: FnMut(&Screen) -> Result<(P, Mode), E>,
+		E: Into<Box<dyn Error + Send + Sync>>,
+	{
+		Self::apply_selected(self.try_select_backgrounds_now(|screen| {
+			let (path, mode) = f(screen)?;
+			Ok::<_, E>(Some((path.as_ref().to_owned(), mode)))
+		}))
+	}
+
+	///Async version of [`Self::set_backgrounds()`].
+	pub fn set_backgrounds_async<F, B>(self, mut f: F) -> impl Future<Output = Result<(), WallpaperError>>
+	where
+		B: Into<Background>,
+		F: FnMut(&Screen) -> (B, Mode),
+	{
+		Self::apply_selected(self.try_select_backgrounds_now(|screen| Ok::<_, Infallible>(Some(f(screen)))))
+	}
+
+	///Async version of [`Self::update_wallpapers()`].
+	pub fn update_wallpapers_async<F, P>(self, mut f: F) -> impl Future<Output = Result<(), WallpaperError>>
+	where
+		P: AsRef<Utf8Path>,
+		F: FnMut(&Screen) -> Option<(P, Mode)>,
+	{
+		Self::apply_selected(self.try_select_backgrounds_now(|screen| {
+			Ok::<_, Infallible>(f(screen).map(|(path, mode)| (path.as_ref().to_owned(), mode)))
+		}))
+	}
+
+	///Async version of [`Self::update_backgrounds()`].
+	pub fn update_backgrounds_async<F, B>(self, mut f: F) -> impl Future<Output = Result<(), WallpaperError>>
+	where
+		B: Into<Background>,
+		F: FnMut(&Screen) -> Option<(B, Mode)>,
+	{
+		Self::apply_selected(self.try_select_backgrounds_now(|screen| Ok::<_, Infallible>(f(screen))))
+	}
+
+	/// Select the backgrounds immediately, so the closure is not part of the returned future.
+	fn try_select_backgrounds_now<F, B, E>(mut self, f: F) -> Result<Self, WallpaperError>
+	where
+		B: Into<Background>,
+		F: FnMut(&Screen) -> Result<Option<(B, Mode)>, E>,
+		E: Into<Box<dyn Error + Send + Sync>>,
+	{
+		self.try_select_backgrounds(f)?;
+		Ok(self)
+	}
+
+	/// apply the selected backgrounds, see [`Self::try_select_backgrounds_now()`]
+	async fn apply_selected(selected: Result<Self, WallpaperError>) -> Result<(), WallpaperError> {
+		selected?.apply_changes_async().await
+	}
+
+	///Async version of [`Self::apply()`].
+	pub async fn apply_async(self) -> Result<(), WallpaperError> {
+		spawn_blocking(self.session.cancel.clone(), || self.apply()).await
+	}
+
+	///Async version of [`Self::plan()`].
+	pub async fn plan_async(self) -> Result<Plan, WallpaperError> {
+		spawn_blocking(self.session.cancel.clone(), || self.plan()).await
+	}
+
+	///Async version of [`Self::set_wallpapers_from_vec()`].
+	pub fn set_wallpapers_from_vec_async<P>(
+		mut self,
+		wallpapers: Vec<P>,
+		default_wallpaper: P,
+		mode: Mode,
+	) -> impl Future<Output = Result<Vec<Utf8PathBuf>, WallpaperError>>
+	where
+		P: AsRef<Utf8Path>,
+	{
+		let used_wallpapers = self.select_wallpapers_from_vec(wallpapers, default_wallpaper, mode);
+		async move {
+			self.apply_changes_async().await?;
+			Ok(used_wallpapers)
+		}
+	}
+
+	///Async version of [`Self::set_random_wallpapers_from_vec()`].
+	#[cfg(feature = "rand")]
+	pub fn set_random_wallpapers_from_vec_async<P>(
+		self,
+		wallpapers: Vec<P>,
+		default_wallpaper: P,
+		mode: Mode,
+	) -> impl Future<Output = Result<Vec<Utf8PathBuf>, WallpaperError>>
+	where
+		P: AsRef<Utf8Path>,
+		P: Clone,
+	{
+		let wallpapers = self.shuffle_wallpapers(wallpapers);
+		self.set_wallpapers_from_vec_async(wallpapers, default_wallpaper, mode)
+	}
+
+	/// pass the changed screens to the environment at the blocking thread pool
+	async fn apply_changes_async(self) -> Result<(), WallpaperError> {
+		spawn_blocking(self.session.cancel.clone(), || self.apply_changes()).await
+	}
+}
+
+impl Plan {
+	///Async version of [`Self::apply()`].
+	pub async fn apply_async(self) -> Result<(), WallpaperError> {
+		spawn_blocking(self.builder.session.cancel.clone(), || self.apply()).await
+	}
+}
+
+impl WallpaperSnapshot {
+	///Async version of [`Self::capture()`].
+	pub async fn capture_async() -> Result<Self, WallpaperError> {
+		Ok(WallpaperBuilder::new_async().await?.snapshot())
+	}
+
+	///Async version of [`Self::restore()`].
+	pub async fn restore_async(self) -> Result<(), WallpaperError> {
+		self.apply_async(false).await
+	}
+
+	///Async version of [`Self::restore_forced()`].
+	pub async fn restore_forced_async(self) -> Result<(), WallpaperError> {
+		self.apply_async(true).await
+	}
+
+	async fn apply_async(self, force: bool) -> Result<(), WallpaperError> {
+		let cancel = CancelToken::new();
+		let options = WallpaperBuilder::options().cancel_token(cancel.clone());
+		spawn_blocking(cancel, move || self.apply(options, force)).await
+	}
+}
+
+///Async version of [`set_wallpapers_from_vec()`](crate::set_wallpapers_from_vec()).
+pub async fn set_wallpapers_from_vec_async<P>(
+	wallpapers: Vec<P>,
+	default_wallpaper: P,
+	mode: Mode,
+) -> Result<Vec<Utf8PathBuf>, WallpaperError>
+where
+	P: AsRef<Utf8Path>,
+{
+	let builder = WallpaperBuilder::new_async().await?;
+	builder
+		.set_wallpapers_from_vec_async(wallpapers, default_wallpaper, mode)
+		.await
+}
+
+///Async version of [`set_random_wallpapers_from_vec()`](crate::set_random_wallpapers_from_vec()).
+#[cfg(feature = "rand")]
+pub async fn set_random_wallpapers_from_vec_async<P>(
+	wallpapers: Vec<P>,
+	default_wallpaper: P,
+	mode: Mode,
+) -> Result<Vec<Utf8PathBuf>, WallpaperError>
+where
+	P: AsRef<Utf8Path>,
+	P: Clone,
+{
+	let builder = WallpaperBuilder::new_async().await?;
+	builder
+		.set_random_wallpapers_from_vec_async(wallpapers, default_wallpaper, mode)
+		.await
+}
+
+///Async version of [`check_requirements()`](crate::check_requirements()).
+pub async fn check_requirements_async() -> Result<Vec<MissingRequirement>, WallpaperError> {
+	spawn_blocking(CancelToken::new(), || Ok(get_environment()?.check_requirements())).await
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{
+		register_backend, Backend, CancelToken, Capabilities, Environment, Mode, Screen, Session, WallpaperBuilder,
+		WallpaperError,
+	};
+	use std::{cell::Cell, rc::Rc, thread, time::Duration};
+
+	struct Slow;
+
+	impl Backend for Slow {
+		fn name(&self) -> &'static str {
+			"slow"
+		}
+
+		fn detect(&self) -> bool {
+			false
+		}
+
+		fn get_screens(&self, _session: &Session) -> Result<Vec<Screen>, WallpaperError> {
+			thread::sleep(Duration::from_millis(500));
+			Ok(Vec::new())
+		}
+
+		fn set_screens(&self, _session: &Session, _screens: Vec<Screen>, _changed: &[bool]) -> Result<(), WallpaperError> {
+			Ok(())
+		}
+
+		fn capabilities(&self) -> Capabilities {
+			Capabilities::default()
+		}
+	}
+
+	#[tokio::test]
+	async fn dropped_future_cancels_token() {
+		register_backend(Slow, i32::MIN);
+		let token = CancelToken::new();
+		let future = WallpaperBuilder::options()
+			.environment(Environment::Custom("slow"))
+			.cancel_token(token.clone())
+			.build_async();
+		let result = tokio::time::timeout(Duration::from_millis(10), future).await;
+		assert!(result.is_err());
+		assert!(token.is_cancelled());
+	}
+
+	fn assert_send<T: Send>(_: &T) {}
+
+	#[test]
+	fn closures_are_called_before_the_future_is_polled() {
+		let screen = Screen {
+			name: "all".to_owned(),
+			background: None,
+			mode: None,
+			fill_color: None,
+			focal_point: None,
+			active: true,
+			primary: true,
+			geometry: None,
+		};
+		let builder = WallpaperBuilder::from_screens(Environment::Custom("slow"), vec![screen], Session::default());
+		// Rc is not Send, so the closure must not be part of the future
+		let called = Rc::new(Cell::new(false));
+		let future = builder.update_wallpapers_async({
+			let called = called.clone();
+			move |_| {
+				called.set(true);
+				None::<(&str, Mode)>
+			}
+		});
+		assert!(called.get());
+		assert_send(&future);
+	}
+}
@@ -0,0 +1,377 @@
+use crate::{
+	builtin_backend, detect_builtin, plan, CancelToken, Capabilities, Environment, Operation, Screen, WallpaperError,
+};
+#[cfg(target_os = "linux")]
+use crate::{linux::DEFAULT_TIMEOUT, CommandRunner, DefaultRunner};
+#[cfg(target_os = "linux")]
+use std::time::Duration;
+use std::{
+	cmp::Reverse,
+	sync::{Arc, Mutex, PoisonError},
+};
+
+/// Interface to read and set the wallpapers of an environment.
+/// The environments supported by this crate are built-in,
+/// additional backends can be added by [`register_backend()`].
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use more_wallpapers::{
+/// 	register_backend, Backend, Capabilities, Mode, Screen, Session, WallpaperBuilder,
+/// 	WallpaperError,
+/// };
+/// use std::process::Command;
+///
+/// struct River;
+///
+/// impl Backend for River {
+/// 	fn name(&self) -> &'static str {
+/// 		"river"
+/// 	}
+///
+/// 	fn detect(&self) -> bool {
+/// 		std::env::var("XDG_CURRENT_DESKTOP").as_deref() == Ok("river")
+/// 	}
+///
+/// 	fn get_screens(&self, _session: &Session) -> Result<Vec<Screen>, WallpaperError> {
+/// 		Ok(vec![Screen {
+/// 			name: "all".to_owned(),
+/// 			background: None,
+/// 			mode: None,
+/// 			fill_color: None,
+/// 			focal_point: None,
+/// 			active: true,
+/// 			primary: true,
+/// 			geometry: None,
+/// 		}])
+/// 	}
+///
+/// 	fn set_screens(
+/// 		&self,
+/// 		session: &Session,
+/// 		screens: Vec<Screen>,
+/// 		_changed: &[bool],
+/// 	) -> Result<(), WallpaperError> {
+/// 		if let Some(wallpaper) = screens.first().and_then(Screen::wallpaper) {
+/// 			session.check_cancelled()?;
+/// 			Command::new("swaybg")
+/// 				.arg("--image")
+/// 				.arg(wallpaper)
+/// 				.spawn()
+/// 				.map_err(|err| WallpaperError::IOError("swaybg".to_owned(), err))?;
+/// 		}
+/// 		Ok(())
+/// 	}
+///
+/// 	fn capabilities(&self) -> Capabilities {
+/// 		let mut capabilities = Capabilities::default();
+/// 		capabilities.modes = &[Mode::Crop];
+/// 		capabilities
+/// 	}
+/// }
+///
+/// register_backend(River, 0);
+/// WallpaperBuilder::new()?.set_wallpapers(|_| ("1.jpg", Mode::Crop))?;
+/// # Ok(())}
+/// ```
+pub trait Backend: Send + Sync {
+	/// Unique name of the backend, witch is used by [`Environment::Custom`].
+	fn name(&self) -> &'static str;
+
+	/// Check if the backend can be used at the current session.
+	fn detect(&self) -> bool;
+
+	/// Return all screens, including the current wallpaper if it is known.
+	fn get_screens(&self, session: &Session) -> Result<Vec<Screen>, WallpaperError>;
+
+	/// Set the wallpapers of the screens.
+	/// `screens` includes all screens with a known wallpaper
+	/// and `changed` indicates for each screen, if it was modified.
+	/// Backends, witch can set screens individually, should only set the changed screens.
+	/// The `session` holds the options of the [`WallpaperBuilder`](crate::WallpaperBuilder), like the cancel token.
+	fn set_screens(&self, session: &Session, screens: Vec<Screen>, changed: &[bool]) -> Result<(), WallpaperError>;
+
+	/// Return the operations, witch [`Self::set_screens()`] would perform, without changing the wallpapers.
+	/// Used by [`WallpaperBuilder::plan()`](crate::WallpaperBuilder::plan).
+	/// The default implementation returns [`WallpaperError::Unsuported`], so planning is refused.
+	fn plan(&self, _session: &Session, _screens: Vec<Screen>, _changed: &[bool]) -> Result<Vec<Operation>, WallpaperError> {
+		Err(WallpaperError::Unsuported(self.name().to_owned()))
+	}
+
+	/// Return the features supported by the backend.
+	fn capabilities(&self) -> Capabilities;
+}
+
+struct Registered {
+	priority: i32,
+	backend: Arc<dyn Backend>,
+}
+
+static BACKENDS: Mutex<Vec<Registered>> = Mutex::new(Vec::new());
+
+/// Register an additional [`Backend`].
+/// Backends are detected starting with the highest priority.
+/// The built-in environments have the priority `0`,
+/// so backends with a negative priority are only used, if no built-in environment was detected.
+/// A backend replaces a already registered backend with the same name.
+pub fn register_backend<B>(backend: B, priority: i32)
+where
+	B: Backend + 'static,
+{
+	let mut backends = BACKENDS.lock().unwrap_or_else(PoisonError::into_inner);
+	backends.retain(|registered| registered.backend.name() != backend.name());
+	backends.push(Registered {
+		priority,
+		backend: Arc::new(backend),
+	});
+	backends.sort_by_key(|registered| Reverse(registered.priority));
+}
+
+/// return the registered backend with the given name
+pub(crate) fn find(name: &str) -> Option<Arc<dyn Backend>> {
+	let backends = BACKENDS.lock().unwrap_or_else(PoisonError::into_inner);
+	backends
+		.iter()
+		.find(|registered| registered.backend.name() == name)
+		.map(|registered| registered.backend.clone())
+}
+
+/// Return the first registered backend, witch can be used at the current session.
+/// If `before_builtin` is true, only backends detected before the built-in environments are checked,
+/// otherwise only the backends detected after them.
+pub(crate) fn detect(before_builtin: bool) -> Option<Arc<dyn Backend>> {
+	// detection can take some time, so the lock is not held during it
+	let backends: Vec<Arc<dyn Backend>> = BACKENDS
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.iter()
+		.filter(|registered| (registered.priority >= BUILTIN_PRIORITY) == before_builtin)
+		.map(|registered| registered.backend.clone())
+		.collect();
+	backends.into_iter().find(|backend| backend.detect())
+}
+
+/// Options of the [`WallpaperBuilder`](crate::WallpaperBuilder), witch are passed to the backends.
+#[derive(Clone, Debug)]
+pub struct Session {
+	/// executes the external programs and D-Bus calls
+	#[cfg(target_os = "linux")]
+	pub(crate) runner: Arc<dyn CommandRunner>,
+	/// maximum duration of a single command or D-Bus call
+	#[cfg(target_os = "linux")]
+	pub(crate) timeout: Duration,
+	pub(crate) cancel: CancelToken,
+	/// read missing focal points from the metadata of the wallpapers
+	#[cfg(target_os = "linux")]
+	pub(crate) focal_point_metadata: bool,
+}
+
+impl Default for Session {
+	fn default() -> Self {
+		Self {
+			#[cfg(target_os = "linux")]
+			runner: Arc::new(DefaultRunner),
+			#[cfg(target_os = "linux")]
+			timeout: DEFAULT_TIMEOUT,
+			cancel: CancelToken::default(),
+			#[cfg(target_os = "linux")]
+			focal_point_metadata: false,
+		}
+	}
+}
+
+impl Session {
+	/// token, witch is configured by [`BuilderOptions::cancel_token()`](crate::BuilderOptions::cancel_token)
+	pub fn cancel_token(&self) -> &CancelToken {
+		&self.cancel
+	}
+
+	/// return [`WallpaperError::Cancelled`], if the token was cancelled
+	pub fn check_cancelled(&self) -> Result<(), WallpaperError> {
+		if self.cancel.is_cancelled() {
+			Err(WallpaperError::Cancelled)
+		} else {
+			Ok(())
+		}
+	}
+}
+
+/// priority of the built-in environments, see [`register_backend()`]
+const BUILTIN_PRIORITY: i32 = 0;
+
+/// read the screens of a built-in environment
+pub(crate) type GetScreens = fn(&Session) -> Result<Vec<Screen>, WallpaperError>;
+/// set the screens of a built-in environment, see [`Backend::set_screens()`]
+pub(crate) type SetScreens = fn(&Session, Vec<Screen>, &[bool]) -> Result<(), WallpaperError>;
+
+/// Backend of a environment supported by this crate.
+/// The capabilities are defined by [`Environment::capabilities()`].
+#[derive(Clone, Copy)]
+pub(crate) struct BuiltinBackend {
+	pub(crate) environment: Environment,
+	pub(crate) get_screens: GetScreens,
+	pub(crate) set_screens: SetScreens,
+}
+
+impl Backend for BuiltinBackend {
+	fn name(&self) -> &'static str {
+		self.environment.name()
+	}
+
+	fn detect(&self) -> bool {
+		detect_builtin() == Some(self.environment)
+	}
+
+	fn get_screens(&self, session: &Session) -> Result<Vec<Screen>, WallpaperError> {
+		(self.get_screens)(session)
+	}
+
+	fn set_screens(&self, session: &Session, screens: Vec<Screen>, changed: &[bool]) -> Result<(), WallpaperError> {
+		(self.set_screens)(session, screens, changed)
+	}
+
+	fn plan(&self, session: &Session, screens: Vec<Screen>, changed: &[bool]) -> Result<Vec<Operation>, WallpaperError> {
+		// the built-in backends record their operations by themselves, while a plan is created
+		let (operations, result) = plan::recording(|| (self.set_screens)(session, screens, changed));
+		result?;
+		Ok(operations)
+	}
+
+	fn capabilities(&self) -> Capabilities {
+		self.environment.capabilities()
+	}
+}
+
+/// return the backend of the environment
+pub(crate) fn backend(environment: Environment) -> Result<Arc<dyn Backend>, WallpaperError> {
+	match environment {
+		Environment::Custom(name) => find(name),
+		environment => builtin_backend(environment).map(|backend| Arc::new(backend) as Arc<dyn Backend>),
+	}
+	.ok_or_else(|| WallpaperError::Unsuported(environment.to_string()))
+}
+
+/// Set the screens with the backend.
+/// If a plan is created, the operations planned by the backend are recorded instead.
+pub(crate) fn set_screens(
+	backend: &dyn Backend,
+	session: &Session,
+	screens: Vec<Screen>,
+	changed: &[bool],
+) -> Result<(), WallpaperError> {
+	if !plan::is_planning() {
+		return backend.set_screens(session, screens, changed);
+	}
+	for operation in backend.plan(session, screens, changed)? {
+		plan::record(|| operation);
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{Color, Mode, WallpaperBuilder};
+
+	/// backend, witch must not be used to set the wallpapers
+	struct Planned(&'static str, bool);
+
+	impl Backend for Planned {
+		fn name(&self) -> &'static str {
+			self.0
+		}
+
+		fn detect(&self) -> bool {
+			false
+		}
+
+		fn get_screens(&self, _session: &Session) -> Result<Vec<Screen>, WallpaperError> {
+			Ok(vec![Screen {
+				name: "all".to_owned(),
+				background: None,
+				mode: None,
+				fill_color: None,
+				focal_point: None,
+				active: true,
+				primary: true,
+				geometry: None,
+			}])
+		}
+
+		fn set_screens(&self, _session: &Session, _screens: Vec<Screen>, _changed: &[bool]) -> Result<(), WallpaperError> {
+			panic!("wallpapers were set while planning");
+		}
+
+		fn plan(
+			&self,
+			_session: &Session,
+			screens: Vec<Screen>,
+			_changed: &[bool],
+		) -> Result<Vec<Operation>, WallpaperError> {
+			if !self.1 {
+				return Err(WallpaperError::Unsuported(self.0.to_owned()));
+			}
+			Ok(screens
+				.iter()
+				.map(|screen| Operation::Command {
+					program: "set-background".to_owned(),
+					args: vec![screen.name.clone()],
+				})
+				.collect())
+		}
+
+		fn capabilities(&self) -> Capabilities {
+			Capabilities::default()
+		}
+	}
+
+	/// backend, witch is always detected
+	struct Detected(&'static str);
+
+	impl Backend for Detected {
+		fn name(&self) -> &'static str {
+			self.0
+		}
+
+		fn detect(&self) -> bool {
+			true
+		}
+
+		fn get_screens(&self, _session: &Session) -> Result<Vec<Screen>, WallpaperError> {
+			Ok(Vec::new())
+		}
+
+		fn set_screens(&self, _session: &Session, _screens: Vec<Screen>, _changed: &[bool]) -> Result<(), WallpaperError> {
+			Ok(())
+		}
+
+		fn capabilities(&self) -> Capabilities {
+			Capabilities::default()
+		}
+	}
+
+	fn plan(name: &'static str) -> Result<Vec<Operation>, WallpaperError> {
+		let mut builder = WallpaperBuilder::with_environment(Environment::Custom(name))?;
+		for mut screen in builder.screens_mut() {
+			screen.set_background(Color::BLACK, Mode::Crop);
+		}
+		Ok(builder.plan()?.operations().to_vec())
+	}
+
+	#[test]
+	fn registered_backends_are_planned_by_the_hook() {
+		register_backend(Planned("planned", true), i32::MIN);
+		register_backend(Planned("unplanned", false), i32::MIN);
+		assert_eq!(plan("planned").unwrap(), vec![Operation::Command {
+			program: "set-background".to_owned(),
+			args: vec!["all".to_owned()],
+		}]);
+		assert!(matches!(plan("unplanned"), Err(WallpaperError::Unsuported(_))));
+	}
+
+	#[test]
+	fn negative_priorities_are_detected_after_the_builtin_backends() {
+		register_backend(Detected("fallback"), -1);
+		assert!(detect(true).is_none());
+		assert_eq!(detect(false).map(|backend| backend.name()), Some("fallback"));
+	}
+}
@@ -0,0 +1,298 @@
+use crate::error::ParseColorError;
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use std::{
+	fmt,
+	fs::{self, File},
+	io::Read,
+	str::FromStr,
+};
+
+/// RGB color
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct Color {
+	pub red: u8,
+	pub green: u8,
+	pub blue: u8,
+}
+
+impl Color {
+	pub const BLACK: Self = Self::new(0, 0, 0);
+	pub const WHITE: Self = Self::new(255, 255, 255);
+
+	pub const fn new(red: u8, green: u8, blue: u8) -> Self {
+		Self { red, green, blue }
+	}
+}
+
+/// parse a color from the format `#rrggbb`
+impl FromStr for Color {
+	type Err = ParseColorError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let hex = s.strip_prefix('#').unwrap_or(s);
+		// from_str_radix accepts a leading sign, so the digits are checked before
+		if hex.len() != 6 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+			return Err(ParseColorError::Format(s.to_owned()));
+		}
+		let channel =
+			|i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|err| ParseColorError::Hex(s.to_owned(), err));
+		Ok(Self::new(channel(0)?, channel(2)?, channel(4)?))
+	}
+}
+
+/// format the color as `#rrggbb`
+impl fmt::Display for Color {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "#{:02x}{:02x}{:02x}", self.red, self.green, self.blue)
+	}
+}
+
+impl TryFrom<String> for Color {
+	type Error = ParseColorError;
+
+	fn try_from(value: String) -> Result<Self, Self::Error> {
+		value.parse()
+	}
+}
+
+impl From<Color> for String {
+	fn from(color: Color) -> Self {
+		color.to_string()
+	}
+}
+
+/// direction in witch the color of a [gradient](Background::Gradient) changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GradientDirection {
+	/// from left to right
+	Horizontal,
+	/// from top to bottom
+	Vertical,
+}
+
+/// background of a screen
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Background {
+	/// wallpaper image
+	Image(Utf8PathBuf),
+	/// single color
+	Color(Color),
+	/// gradient between two colors
+	Gradient {
+		direction: GradientDirection,
+		start: Color,
+		end: Color,
+	},
+}
+
+impl Background {
+	/// path of the wallpaper, if the background is an image
+	pub fn image(&self) -> Option<&Utf8Path> {
+		match self {
+			Self::Image(path) => Some(path),
+			_ => None,
+		}
+	}
+
+	/// Create a color background from the settings of an environment.
+	/// A gradient is only created if the direction and both colors are known.
+	#[cfg(target_os = "linux")]
+	pub(crate) fn from_colors(
+		direction: Option<GradientDirection>,
+		start: Option<Color>,
+		end: Option<Color>,
+	) -> Option<Self> {
+		match (direction, start, end) {
+			(Some(direction), Some(start), Some(end)) => Some(Self::Gradient { direction, start, end }),
+			(_, start, _) => start.map(Self::Color),
+		}
+	}
+}
+
+impl From<Utf8PathBuf> for Background {
+	fn from(path: Utf8PathBuf) -> Self {
+		Self::Image(path)
+	}
+}
+
+impl From<&Utf8Path> for Background {
+	fn from(path: &Utf8Path) -> Self {
+		Self::Image(path.to_owned())
+	}
+}
+
+impl From<Color> for Background {
+	fn from(color: Color) -> Self {
+		Self::Color(color)
+	}
+}
+
+/// Point of an image, witch should stay visible if the image is cropped by [`Mode::Crop`](crate::Mode::Crop).
+/// The coordinates are relative to the image size, so `(0.0, 0.0)` is the top left corner
+/// and `(1.0, 1.0)` is the bottom right corner of the image.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct FocalPoint {
+	pub x: f32,
+	pub y: f32,
+}
+
+impl FocalPoint {
+	pub fn new(x: f32, y: f32) -> Self {
+		Self {
+			x: x.clamp(0.0, 1.0),
+			y: y.clamp(0.0, 1.0),
+		}
+	}
+
+	/// Read the focal point from the first [MWG region](https://www.metadataworkinggroup.org/) (`mwg-rs`) of the image.
+	/// A XMP sidecar file (`image.jpg.xmp` or `image.xmp`) is preferred over the XMP data embedded in the image.
+	/// Return `None` if the image does not have any region or can not be read, like a missing file or a directory.
+	pub fn from_metadata(path: &Utf8Path) -> Option<Self> {
+		let mut sidecars = vec![Utf8PathBuf::from(format!("{path}.xmp"))];
+		if path.extension().is_some() {
+			sidecars.push(path.with_extension("xmp"));
+		}
+		if let Some(sidecar) = sidecars.into_iter().find(|sidecar| sidecar.is_file()) {
+			return Self::from_xmp(&fs::read_to_string(sidecar).ok()?);
+		}
+		if !path.is_file() {
+			return None;
+		}
+		// the xmp packet is stored near the beginning of jpeg and png files
+		let mut buffer = Vec::new();
+		File::open(path)
+			.and_then(|file| file.take(1024 * 1024).read_to_end(&mut buffer))
+			.ok()?;
+		Self::from_xmp(&String::from_utf8_lossy(&buffer))
+	}
+
+	/// Parse the center of the first region of the xmp data.
+	/// The values can be written as attributes (`stArea:x="0.5"`) or as elements (`<stArea:x>0.5</stArea:x>`).
+	/// Regions with `stArea:unit="pixel"` are converted by the `mwg-rs:AppliedToDimensions` of the region list.
+	fn from_xmp(xmp: &str) -> Option<Self> {
+		let start = xmp.find("<mwg-rs:Area")?;
+		let area = &xmp[start..];
+		let end = area
+			.find("/>")
+			.into_iter()
+			.chain(area.find("</mwg-rs:Area>"))
+			.min()
+			.unwrap_or(area.len());
+		let area = &area[..end];
+		let x: f32 = xmp_value(area, "stArea:x")?.parse().ok()?;
+		let y: f32 = xmp_value(area, "stArea:y")?.parse().ok()?;
+		match xmp_value(area, "stArea:unit") {
+			None | Some("normalized") => Some(Self::new(x, y)),
+			Some("pixel") => {
+				let start = xmp.find("<mwg-rs:AppliedToDimensions")?;
+				let dimensions = &xmp[start..];
+				let width: f32 = xmp_value(dimensions, "stDim:w")?.parse().ok()?;
+				let height: f32 = xmp_value(dimensions, "stDim:h")?.parse().ok()?;
+				(width > 0.0 && height > 0.0).then(|| Self::new(x / width, y / height))
+			},
+			Some(_) => None,
+		}
+	}
+}
+
+/// Return the first value of the xmp property `name` in `xmp`,
+/// witch is written as attribute (`name="value"`) or as element (`<name>value</name>`).
+fn xmp_value<'a>(xmp: &'a str, name: &str) -> Option<&'a str> {
+	let attribute = format!("{name}=\"");
+	let element = format!("<{name}>");
+	let (start, end) = match (xmp.find(&attribute), xmp.find(&element)) {
+		(Some(attribute_start), Some(element_start)) if element_start < attribute_start => {
+			(element_start + element.len(), '<')
+		},
+		(Some(start), _) => (start + attribute.len(), '"'),
+		(None, Some(start)) => (start + element.len(), '<'),
+		(None, None) => return None,
+	};
+	let value = &xmp[start..];
+	Some(value[..value.find(end)?].trim())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn color_from_str() {
+		let cases = [
+			("#268bd2", Some(Color::new(0x26, 0x8b, 0xd2))),
+			("268BD2", Some(Color::new(0x26, 0x8b, 0xd2))),
+			("#000000", Some(Color::BLACK)),
+			("#ffffff", Some(Color::WHITE)),
+			("#fff", None),
+			("#268bd2ff", None),
+			("#26+bd2", None),
+			("#2g8bd2", None),
+			("#26 8bd", None),
+			("#ä8bd2", None),
+			("", None),
+		];
+		for (s, color) in cases {
+			assert_eq!(s.parse().ok(), color, "{s}");
+		}
+		let color = Color::new(0, 43, 54);
+		assert_eq!(color.to_string().parse::<Color>().unwrap(), color);
+		assert_eq!(serde_json::to_string(&color).unwrap(), "\"#002b36\"");
+		assert!(serde_json::from_str::<Color>(r#""red""#).is_err());
+	}
+
+	#[test]
+	fn focal_point_from_xmp() {
+		let cases: &[(&str, Option<FocalPoint>)] = &[
+			(
+				r#"<mwg-rs:Area stArea:x="0.25" stArea:y="0.75" stArea:w="0.1" stArea:h="0.1" stArea:unit="normalized"/>"#,
+				Some(FocalPoint::new(0.25, 0.75)),
+			),
+			(
+				"<mwg-rs:Area>\n<stArea:x>0.5</stArea:x>\n<stArea:y> 0.1 </stArea:y>\n</mwg-rs:Area>",
+				Some(FocalPoint::new(0.5, 0.1)),
+			),
+			(
+				r#"<mwg-rs:AppliedToDimensions stDim:w="4000" stDim:h="2000" stDim:unit="pixel"/>
+				<mwg-rs:Area stArea:x="1000" stArea:y="1500" stArea:unit="pixel"/>"#,
+				Some(FocalPoint::new(0.25, 0.75)),
+			),
+			(
+				"<mwg-rs:AppliedToDimensions><stDim:w>400</stDim:w><stDim:h>200</stDim:h></mwg-rs:AppliedToDimensions>\
+				 <mwg-rs:Area><stArea:x>100</stArea:x><stArea:y>50</stArea:y><stArea:unit>pixel</stArea:unit></mwg-rs:Area>",
+				Some(FocalPoint::new(0.25, 0.25)),
+			),
+			// the first region is used
+			(
+				r#"<mwg-rs:Area stArea:x="0.1" stArea:y="0.2"/><mwg-rs:Area stArea:x="0.9" stArea:y="0.9"/>"#,
+				Some(FocalPoint::new(0.1, 0.2)),
+			),
+			// values outside of the image are clamped
+			(
+				r#"<mwg-rs:Area stArea:x="1.5" stArea:y="-1"/>"#,
+				Some(FocalPoint::new(1.0, 0.0)),
+			),
+			// pixel values without the image dimensions can not be converted
+			(r#"<mwg-rs:Area stArea:x="1000" stArea:y="1500" stArea:unit="pixel"/>"#, None),
+			(r#"<mwg-rs:Area stArea:x="0.5" stArea:y="0.5" stArea:unit="inch"/>"#, None),
+			(r#"<mwg-rs:Area stArea:x="0.5"/>"#, None),
+			(r#"<mwg-rs:Area stArea:x="a" stArea:y="0.5"/>"#, None),
+			(r#"<mwg-rs:Area stArea:x="0.5 stArea:y="0.5"#, None),
+			(r#"<x:xmpmeta><rdf:Description dc:title="wallpaper"/></x:xmpmeta>"#, None),
+			("", None),
+		];
+		for (xmp, focal_point) in cases {
+			assert_eq!(FocalPoint::from_xmp(xmp), *focal_point, "{xmp}");
+		}
+	}
+
+	#[test]
+	fn focal_point_from_missing_metadata() {
+		assert_eq!(FocalPoint::from_metadata(Utf8Path::new("/does/not/exist.jpg")), None);
+		// KDE wallpapers are package directories
+		assert_eq!(FocalPoint::from_metadata(Utf8Path::new(env!("CARGO_MANIFEST_DIR"))), None);
+	}
+}
@@ -0,0 +1,47 @@
+use std::sync::{
+	atomic::{AtomicBool, Ordering},
+	Arc,
+};
+
+/// Cancel reading or setting the wallpapers from a different thread.
+/// The token is passed to [`BuilderOptions::cancel_token()`](crate::BuilderOptions::cancel_token).
+///
+/// A timeout does only limit the duration of a single command.
+/// A user interface, witch sets wallpapers of a unresponsive desktop, must be able to abort all following commands too.
+/// After cancellation the running command is killed, the running D-Bus call is abandoned
+/// and [`WallpaperError::Cancelled`](crate::WallpaperError::Cancelled) is returned.
+/// Screens, witch were already changed, keep their new wallpaper.
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use more_wallpapers::{CancelToken, Mode, WallpaperBuilder};
+/// use std::{thread, time::Duration};
+///
+/// let token = CancelToken::new();
+/// let cancel = token.clone();
+/// thread::spawn(move || {
+/// 	thread::sleep(Duration::from_secs(5));
+/// 	cancel.cancel();
+/// });
+/// WallpaperBuilder::options()
+/// 	.cancel_token(token)
+/// 	.build()?
+/// 	.set_wallpapers(|_| ("1.jpg", Mode::Crop))?;
+/// # Ok(())}
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Cancel all operations, witch use this token or a clone of it.
+	pub fn cancel(&self) {
+		self.0.store(true, Ordering::Relaxed);
+	}
+
+	pub fn is_cancelled(&self) -> bool {
+		self.0.load(Ordering::Relaxed)
+	}
+}
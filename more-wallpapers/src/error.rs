@@ -1,10 +1,12 @@
 #[cfg(target_os = "linux")]
 use std::env;
-use std::io;
 #[cfg(target_os = "linux")]
-use std::{ffi::OsString, process::Command};
+use std::{ffi::OsString, process::Command, time::Duration};
+use std::{io, num::ParseIntError};
 use thiserror::Error;
 
+use crate::Environment;
+
 #[cfg(target_os = "linux")]
 use serde_json;
 #[cfg(target_os = "linux")]
@@ -20,16 +22,27 @@ pub enum CommandError {
 	#[cfg(target_os = "linux")]
 	#[error("{command:?} exit with code {exit_code:?}:\n{}", String::from_utf8_lossy(.stderr))]
 	CommandStatus {
-		command: Command,
+		command: Box<Command>,
 		exit_code: Option<i32>,
 		stderr: Vec<u8>,
 	},
 }
 
+/// Runtime dependency of a environment, witch was not found.
+/// Returned by [`Environment::check_requirements()`].
+#[derive(Clone, Debug, Error)]
+#[error("{tool} is required by environment {environment}, but was not found: {hint}")]
+pub struct MissingRequirement {
+	/// name of the missing program or service
+	pub tool: String,
+	pub environment: Environment,
+	/// how the dependency can be installed
+	pub hint: String,
+}
+
 #[derive(Debug, Error)]
 #[non_exhaustive]
 pub enum WallpaperError {
-	#[cfg(target_os = "linux")]
 	#[error("unsupported Desktop Environment {0:?}")]
 	Unsuported(String),
 
@@ -57,12 +70,88 @@ pub enum WallpaperError {
 	#[error("{0}")]
 	WallpaperCrate(#[from] fallback::Error),
 
+	#[cfg(target_os = "linux")]
+	#[error("{program:?} did not finish within {elapsed:?}")]
+	Timeout { program: String, elapsed: Duration },
+
+	#[error("operation was cancelled")]
+	Cancelled,
+
+	#[cfg(target_os = "linux")]
+	#[error("{0}")]
+	MissingTool(#[from] MissingRequirement),
+
 	#[error("{0:?} {1}")]
 	IOError(String, io::Error),
 
+	#[error("screen {0:?} does not exist")]
+	UnknownScreen(String),
+
+	#[error("no wallpaper selected for screen {0:?}")]
+	MissingWallpaper(String),
+
+	#[error("screen {screen:?} has no {missing}")]
+	IncompleteScreen { screen: String, missing: &'static str },
+
+	#[cfg(target_os = "linux")]
+	#[error("{program} returned invalid output: {error}")]
+	InvalidOutput {
+		program: String,
+		#[source]
+		error: Box<dyn std::error::Error + Send + Sync>,
+	},
+
+	#[error("failed to select wallpaper: {0}")]
+	Selection(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+	#[cfg(target_os = "linux")]
+	#[error("{error}{}", rollback_message(.rollback_errors))]
+	Rollback {
+		#[source]
+		error: Box<WallpaperError>,
+		/// errors, witch occurred while resetting the already changed screens
+		rollback_errors: Vec<WallpaperError>,
+	},
+
+	#[error("snapshot was captured at environment {snapshot}, but current environment is {current}")]
+	EnvironmentMismatch { snapshot: Environment, current: Environment },
+
 	#[cfg(target_os = "linux")]
 	#[error("Unknow XFCE wallpaper mode {0:?}")]
 	UnknownMode(String),
+
+	#[cfg(target_os = "linux")]
+	#[error("position of screen {0:?} is unknown")]
+	UnknownGeometry(String),
+
+	#[cfg(target_os = "linux")]
+	#[error("can not determine size of image {0:?}")]
+	UnknownImageSize(camino::Utf8PathBuf),
+
+	#[cfg(target_os = "linux")]
+	#[error("failed to parse color: {0}")]
+	ParseColor(#[from] ParseColorError),
+
+	#[cfg(any(target_os = "macos", target_os = "windows"))]
+	#[error("only images are supported as background")]
+	UnsupportedBackground,
+}
+
+#[derive(Debug, Error)]
+pub enum ParseColorError {
+	#[error("color {0:?} does not match the format `#rrggbb`")]
+	Format(String),
+
+	#[error("color {0:?} is not a valid hex number: {1}")]
+	Hex(String, ParseIntError),
+}
+
+#[cfg(target_os = "linux")]
+fn rollback_message(rollback_errors: &[WallpaperError]) -> String {
+	rollback_errors
+		.iter()
+		.map(|error| format!("\nfailed to reset screen: {error}"))
+		.collect()
 }
 
 pub(crate) trait Context<V> {
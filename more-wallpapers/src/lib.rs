@@ -32,7 +32,15 @@
 //! ³ Wallpapers will be reset after restart. <br/>
 //! ⁴ Wallpapers will be reset to provided default after restart.
 //!
-//! The information about the currently supported features are also provided by the [`Environment`] enum.
+//! Modes, which are not natively supported by the environment (see [`Capabilities::modes`]),
+//! and color or gradient [backgrounds](Background), which are not natively supported,
+//! are emulated by pre-rendered images. This requires [ImageMagick].
+//!
+//! The information about the currently supported features are also provided by [`Environment::capabilities()`].
+//!
+//! The environment is detected automatically.
+//! At linux the detection can be overridden by setting the `MORE_WALLPAPERS_ENVIRONMENT` environment variable
+//! to the lowercase name of the [environment](Environment), like `sway` or `kde`.
 //!
 //! ## QuickStart / Examples:
 //! If you would like to set only a different wallpaper for each screen and don't care
@@ -51,12 +59,18 @@
 //!
 //! For advanced wallpaper settings you can use the [`WallpaperBuilder`]:
 #![doc = doc_WallpaperBuilder_example!()]
+//!
+//! Applications using [tokio](https://tokio.rs) can enable the `async` feature,
+//! witch provides [async versions](asynchronous) of the blocking functions.
+//! The `tracing` feature instruments the crate with [tracing](https://crates.io/crates/tracing) spans and events,
+//! for example for every executed command.
 //!  [wallpaper]: https://crates.io/crates/wallpaper
 //!  [wall]: https://crates.io/crates/wall
 //!  [xwallpaper]: https://github.com/stoeckmann/xwallpaper
 //!  [libxrandr]: https://gitlab.freedesktop.org/xorg/app/xrandr
 //!  [dbus]: https://gitlab.freedesktop.org/dbus/dbus
 //!  [swaybg]: https://github.com/swaywm/swaybg
+//!  [ImageMagick]: https://imagemagick.org
 
 macro_rules! doc_WallpaperBuilder_example {
 	() => {
@@ -97,7 +111,9 @@ macro_rules! doc_set_wallpapers_from_vec {
 		(@private head) => {
 			r#"
 Set the background of all screens to the wallpapers of `wallpapers`.
-The wallpaper of `screen[i]` will be set to `wallpapers[i mod wallpapers.len()]`.
+The first wallpaper is set at the [primary screen](Screen::primary),
+the following wallpapers are set at the other active screens from left to right.
+If there are more screens than wallpapers, the wallpapers are repeated.
 The `default_wallpaper` param is used if the given `wallpapers` vec is empty and as wallpaper for [inactive screens](Screen::active).
 Return a vec, with dose inlcude the path of the Wallpapers,
 witch was set as background.
@@ -121,16 +137,33 @@ println!("background was set to the following wallpapers {used_wallpapers:?}");
 		};
 	}
 
+#[cfg(feature = "async")]
+pub mod asynchronous;
+mod backend;
+mod background;
+mod cancel;
 mod error;
+mod plan;
+mod snapshot;
 
+pub use backend::{register_backend, Backend, Session};
+pub use background::{Background, Color, FocalPoint, GradientDirection};
 use camino::{Utf8Path, Utf8PathBuf};
+pub use cancel::CancelToken;
 #[cfg(target_os = "linux")]
 use error::load_env_var;
 #[cfg(target_os = "linux")]
 pub use error::CommandError;
 use error::Context;
-pub use error::WallpaperError;
-use std::io;
+pub use error::{MissingRequirement, ParseColorError, WallpaperError};
+#[cfg(target_os = "linux")]
+pub use linux::{CommandRunner, DefaultRunner, DetectionReport, FlatpakRunner, RunContext};
+pub use plan::{DBusCall, Operation, Plan};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
+pub use snapshot::WallpaperSnapshot;
+use std::{convert::Infallible, error::Error, fmt, io, ops::Deref};
+#[cfg(target_os = "linux")]
+use std::{sync::Arc, time::Duration};
 use strum_macros::{Display, EnumString};
 
 #[cfg(feature = "rand")]
@@ -143,22 +176,20 @@ use crate::linux::*;
 
 #[cfg(all(target_os = "windows", not(feature = "fallback")))]
 std::compile_error!("Windows does need the \"wallpaper\" feature");
-#[cfg(target_os = "windows")]
-mod windows;
-#[cfg(target_os = "windows")]
-use crate::windows::*;
-
 #[cfg(all(target_os = "macos", not(feature = "fallback")))]
 std::compile_error!("MacOS does need the \"wallpaper\" feature");
-#[cfg(target_os = "macos")]
-mod macos;
-#[cfg(target_os = "macos")]
-use crate::macos::*;
+#[cfg(any(target_os = "macos", target_os = "windows", all(target_os = "linux", feature = "fallback")))]
+mod wallpaper_crate;
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+use crate::wallpaper_crate::*;
 
 /// define how the wallpaper will be stretch, zoom, repeated etc
-#[derive(Debug, Clone, Copy, Default, EnumString, Display, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Default, EnumString, Display, PartialEq, Eq, Deserialize, Serialize)]
 #[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
 pub enum Mode {
+	///zoom image like [`Mode::Fit`], but fill the empty space with a blurred copy of the image, witch is zoomed like [`Mode::Crop`].
+	BlurFill,
 	///center image witout zooming. Image is may not full visible. Empty space is filled with black.
 	Center,
 	///zoom image to match x and y size of display and keep aspect ratio. Some parts of the image is may cut off.
@@ -166,6 +197,8 @@ pub enum Mode {
 	Crop,
 	///zoom image to match match x or y size of the display, the other will be filled with a black bar at each side. All parts of the immages are visible.
 	Fit,
+	///Span one image over all screens. The union of all active screens is used as canvas, witch is filled like [`Mode::Crop`].
+	Span,
 	///zoom x and y independently from each other to match display size.
 	Stretch,
 	///Repeat the image until the Screen is filled. May not all parts of the image are visible.
@@ -176,9 +209,11 @@ pub enum Mode {
 impl From<Mode> for fallback::Mode {
 	fn from(mode: Mode) -> Self {
 		match mode {
+			Mode::BlurFill => fallback::Mode::Fit,
 			Mode::Center => fallback::Mode::Center,
 			Mode::Crop => fallback::Mode::Crop,
 			Mode::Fit => fallback::Mode::Fit,
+			Mode::Span => fallback::Mode::Span,
 			Mode::Stretch => fallback::Mode::Stretch,
 			Mode::Tile => fallback::Mode::Tile,
 		}
@@ -187,12 +222,20 @@ impl From<Mode> for fallback::Mode {
 
 /// Represent the used operating system or desktop.
 /// Inform about supported features, at the curren environment.
-#[derive(Debug, Clone, Copy, Display, PartialEq, Eq)]
-#[strum(serialize_all = "lowercase")]
+///
+/// The environment is detected automatically.
+/// At linux the detection can be overridden by the `MORE_WALLPAPERS_ENVIRONMENT` environment variable,
+/// witch accepts the lowercase name of the environment, like `sway` or `kde`.
+#[derive(Debug, Clone, Copy, EnumString, PartialEq, Eq, Serialize)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+#[serde(into = "String")]
 #[non_exhaustive]
 pub enum Environment {
 	#[cfg(target_os = "linux")]
 	Cinnamon,
+	/// environment of a [registered backend](register_backend)
+	#[strum(disabled)]
+	Custom(&'static str),
 	#[cfg(target_os = "linux")]
 	Kde,
 	#[cfg(target_os = "linux")]
@@ -208,54 +251,440 @@ pub enum Environment {
 	#[cfg(target_os = "linux")]
 	Xfce,
 }
+
+// the name of custom environments is only known at runtime, so strum can not be used
+impl fmt::Display for Environment {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.name())
+	}
+}
+
+impl TryFrom<String> for Environment {
+	type Error = String;
+
+	fn try_from(value: String) -> Result<Self, Self::Error> {
+		value
+			.parse()
+			.ok()
+			.or_else(|| backend::find(&value).map(|backend| Self::Custom(backend.name())))
+			.ok_or_else(|| format!("unknown environment {value:?}"))
+	}
+}
+
+impl From<Environment> for String {
+	fn from(value: Environment) -> Self {
+		value.to_string()
+	}
+}
+
+// custom backends are not known at compile time, so the derive macro can not be used
+impl<'de> Deserialize<'de> for Environment {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		String::deserialize(deserializer)?.try_into().map_err(D::Error::custom)
+	}
+}
+
 impl Environment {
-	///return true, if the current environment does support various wallpaper on each screen
-	pub fn support_various_wallpaper(&self) -> bool {
+	/// name of the environment, witch is also used by `MORE_WALLPAPERS_ENVIRONMENT`
+	pub fn name(&self) -> &'static str {
+		match self {
+			#[cfg(target_os = "linux")]
+			Self::Cinnamon => "cinnamon",
+			Self::Custom(name) => name,
+			#[cfg(target_os = "linux")]
+			Self::Kde => "kde",
+			#[cfg(target_os = "linux")]
+			Self::Sway => "sway",
+			#[cfg(all(target_os = "linux", feature = "fallback"))]
+			Self::LinuxFallback => "linuxfallback",
+			#[cfg(all(target_os = "macos", feature = "fallback"))]
+			Self::MacOS => "macos",
+			#[cfg(all(target_os = "windows", feature = "fallback"))]
+			Self::Windows => "windows",
+			#[cfg(target_os = "linux")]
+			Self::X11 => "x11",
+			#[cfg(target_os = "linux")]
+			Self::Xfce => "xfce",
+		}
+	}
+
+	///Return all missing runtime dependencies of the environment.
+	///This includes optional dependencies like ImageMagick, witch is only required for some backgrounds.
+	pub fn check_requirements(&self) -> Vec<MissingRequirement> {
+		missing_requirements(*self)
+	}
+
+	///return the features supported by the environment
+	pub fn capabilities(&self) -> Capabilities {
+		const BASIC_MODES: &[Mode] = &[Mode::Center, Mode::Crop, Mode::Fit, Mode::Stretch, Mode::Tile];
+		const WITH_SPAN: &[Mode] = &[Mode::Center, Mode::Crop, Mode::Fit, Mode::Span, Mode::Stretch, Mode::Tile];
 		match self {
+			Self::Custom(name) => backend::find(name).map(|backend| backend.capabilities()).unwrap_or_default(),
 			#[cfg(target_os = "linux")]
-			Self::Cinnamon => true,
+			Self::Cinnamon => Capabilities {
+				various_wallpaper: true,
+				modes: WITH_SPAN,
+				read_wallpaper: true,
+				persistent: false,
+				lock_screen: false,
+				per_workspace: false,
+			},
 			#[cfg(target_os = "linux")]
-			Self::Kde => true,
+			Self::Kde => Capabilities {
+				various_wallpaper: true,
+				modes: &[Mode::BlurFill, Mode::Center, Mode::Crop, Mode::Fit, Mode::Stretch, Mode::Tile],
+				read_wallpaper: true,
+				persistent: true,
+				lock_screen: false,
+				per_workspace: false,
+			},
 			#[cfg(target_os = "linux")]
-			Self::Sway => true,
+			Self::Sway => Capabilities {
+				various_wallpaper: true,
+				modes: BASIC_MODES,
+				read_wallpaper: true,
+				persistent: false,
+				lock_screen: false,
+				per_workspace: false,
+			},
 			#[cfg(all(target_os = "linux", feature = "fallback"))]
-			Self::LinuxFallback => false,
+			Self::LinuxFallback => Capabilities {
+				various_wallpaper: false,
+				modes: WITH_SPAN,
+				read_wallpaper: true,
+				persistent: true,
+				lock_screen: false,
+				per_workspace: false,
+			},
 			#[cfg(all(target_os = "macos", feature = "fallback"))]
-			Self::MacOS => false,
+			Self::MacOS => Capabilities {
+				various_wallpaper: false,
+				modes: WITH_SPAN,
+				read_wallpaper: true,
+				persistent: true,
+				lock_screen: false,
+				per_workspace: false,
+			},
 			#[cfg(all(target_os = "windows", feature = "fallback"))]
-			Self::Windows => false,
+			Self::Windows => Capabilities {
+				various_wallpaper: false,
+				modes: WITH_SPAN,
+				read_wallpaper: true,
+				persistent: true,
+				lock_screen: false,
+				per_workspace: false,
+			},
 			#[cfg(target_os = "linux")]
-			Self::X11 => true,
+			Self::X11 => Capabilities {
+				various_wallpaper: true,
+				modes: WITH_SPAN,
+				read_wallpaper: true,
+				persistent: false,
+				lock_screen: false,
+				per_workspace: false,
+			},
 			#[cfg(target_os = "linux")]
-			Self::Xfce => true,
+			Self::Xfce => Capabilities {
+				various_wallpaper: true,
+				modes: WITH_SPAN,
+				read_wallpaper: true,
+				persistent: true,
+				lock_screen: false,
+				per_workspace: true,
+			},
 		}
 	}
 }
 
+/// Features supported by an [`Environment`].
+/// Can be used to adapt the user interface to the current environment.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Capabilities {
+	/// different wallpapers can be set at each screen
+	pub various_wallpaper: bool,
+	/// modes, which are natively supported by the environment
+	pub modes: &'static [Mode],
+	/// the current wallpaper and mode can be read back by [`WallpaperBuilder::screens()`].
+	/// Some environments can only report the wallpaper, witch was last set by this crate.
+	pub read_wallpaper: bool,
+	/// the wallpapers are kept after restart
+	pub persistent: bool,
+	/// the background of the lock screen can be set
+	pub lock_screen: bool,
+	/// different wallpapers can be set per workspace
+	pub per_workspace: bool,
+}
+
+/// position and size of a screen in pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Rectangle {
+	pub x: i32,
+	pub y: i32,
+	pub width: u32,
+	pub height: u32,
+}
+
 /// include information about a connected screen
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Screen {
 	pub name: String,
-	/// current wallpaper of the screen
-	pub wallpaper: Option<Utf8PathBuf>,
+	/// current background of the screen
+	pub background: Option<Background>,
 	/// current mode of the screen
 	pub mode: Option<Mode>,
+	/// color witch fills the empty space left by [`Mode::Fit`] and [`Mode::Center`].
+	/// `None` means the default of the environment, which is normally black.
+	pub fill_color: Option<Color>,
+	/// point of the wallpaper, witch should stay visible if [`Mode::Crop`] is used.
+	/// If `None` and [`BuilderOptions::focal_point_metadata()`] is enabled,
+	/// the focal point is read from the [metadata](FocalPoint::from_metadata) of the wallpaper.
+	pub focal_point: Option<FocalPoint>,
 	/// indicates if screen is active.
 	/// A inactive screen is current disconneted or repesents a default for new connected screens or is a fallback after restart
 	pub active: bool,
+	/// indicates if screen is the primary screen.
+	/// If the environment does not know a primary screen, this is `false` for all screens.
+	pub primary: bool,
+	/// position and size of the screen, if known
+	pub geometry: Option<Rectangle>,
+}
+
+impl Screen {
+	/// path of the current wallpaper, if the background is an image
+	pub fn wallpaper(&self) -> Option<&Utf8Path> {
+		self.background.as_ref().and_then(Background::image)
+	}
+
+	/// check if background and mode are known, so the screen can be passed to a backend
+	pub(crate) fn is_set(&self) -> bool {
+		match &self.background {
+			Some(Background::Image(_)) => self.mode.is_some(),
+			background => background.is_some(),
+		}
+	}
+
+	/// return the background or [`WallpaperError::IncompleteScreen`], if it is not set
+	pub(crate) fn require_background(&self) -> Result<&Background, WallpaperError> {
+		self.background.as_ref().ok_or_else(|| WallpaperError::IncompleteScreen {
+			screen: self.name.clone(),
+			missing: "background",
+		})
+	}
+
+	/// return the mode or [`WallpaperError::IncompleteScreen`], if it is not set
+	pub(crate) fn require_mode(&self) -> Result<Mode, WallpaperError> {
+		self.mode.ok_or_else(|| WallpaperError::IncompleteScreen {
+			screen: self.name.clone(),
+			missing: "mode",
+		})
+	}
+}
+
+/// mutable access to a [`Screen`] of a [`WallpaperBuilder`].
+/// Every modification marks the screen as changed.
+#[derive(Debug)]
+pub struct ScreenMut<'a> {
+	screen: &'a mut Screen,
+	changed: &'a mut bool,
+}
+
+impl Deref for ScreenMut<'_> {
+	type Target = Screen;
+
+	fn deref(&self) -> &Screen {
+		self.screen
+	}
+}
+
+impl ScreenMut<'_> {
+	/// set the wallpaper and mode of the screen
+	pub fn set<P>(&mut self, wallpaper: P, mode: Mode) -> &mut Self
+	where
+		P: AsRef<Utf8Path>,
+	{
+		self.set_background(wallpaper.as_ref().to_owned(), mode)
+	}
+
+	/// set the background and mode of the screen
+	pub fn set_background<B>(&mut self, background: B, mode: Mode) -> &mut Self
+	where
+		B: Into<Background>,
+	{
+		self.screen.background = Some(background.into());
+		self.screen.mode = Some(mode);
+		*self.changed = true;
+		self
+	}
+
+	/// set the [fill color](Screen::fill_color) of the screen
+	pub fn set_fill_color(&mut self, fill_color: Option<Color>) -> &mut Self {
+		self.screen.fill_color = fill_color;
+		*self.changed = true;
+		self
+	}
+
+	/// set the [focal point](Screen::focal_point) of the screen
+	pub fn set_focal_point(&mut self, focal_point: Option<FocalPoint>) -> &mut Self {
+		self.screen.focal_point = focal_point;
+		*self.changed = true;
+		self
+	}
 }
 
 ///Builder for advance Wallpaper settings and informations.
 ///This struct should not be stored for a long time, because it can become outdated if the user connect or disconnect monitors or change the Display settings.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct WallpaperBuilder {
 	screens: Vec<Screen>,
 	environment: Environment,
+	/// indicates for each screen, if it should be changed
+	changed: Vec<bool>,
+	session: Session,
+}
+
+///Options to create a [`WallpaperBuilder`].
+///```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use more_wallpapers::{Environment, FlatpakRunner, Mode, WallpaperBuilder};
+///
+/// WallpaperBuilder::options()
+/// 	.environment(Environment::Xfce)
+/// 	.runner(FlatpakRunner)
+/// 	.build()?
+/// 	.set_wallpapers(|_| ("1.jpg", Mode::Crop))?;
+/// # Ok(())}
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct BuilderOptions {
+	environment: Option<Environment>,
+	#[cfg(target_os = "linux")]
+	runner: Option<Arc<dyn CommandRunner>>,
+	#[cfg(target_os = "linux")]
+	timeout: Option<Duration>,
+	cancel: CancelToken,
+	#[cfg(target_os = "linux")]
+	focal_point_metadata: bool,
+}
+
+impl BuilderOptions {
+	///Use the given environment instead of detecting it.
+	pub fn environment(mut self, environment: Environment) -> Self {
+		self.environment = Some(environment);
+		self
+	}
+
+	///Execute the external programs by the given [`CommandRunner`], instead of the [`DefaultRunner`].
+	#[cfg(target_os = "linux")]
+	pub fn runner<R>(mut self, runner: R) -> Self
+	where
+		R: CommandRunner + 'static,
+	{
+		self.runner = Some(Arc::new(runner));
+		self
+	}
+
+	///Set the maximum duration of a single operation, like executing a external program or a D-Bus call.
+	///Programs, witch does not finish in time, are killed and [`WallpaperError::Timeout`] is returned.
+	///The default is 30 seconds.
+	///```
+	/// use more_wallpapers::{
+	/// 	CommandRunner, DefaultRunner, Environment, RunContext, WallpaperBuilder, WallpaperError,
+	/// };
+	/// use std::{process::Command, time::Duration};
+	///
+	/// struct Hanging;
+	///
+	/// impl CommandRunner for Hanging {
+	/// 	fn run(&self, _command: Command, context: &RunContext) -> Result<Vec<u8>, WallpaperError> {
+	/// 		let mut sleep = Command::new("sleep");
+	/// 		sleep.arg("10");
+	/// 		DefaultRunner.run(sleep, context)
+	/// 	}
+	/// }
+	///
+	/// let result = WallpaperBuilder::options()
+	/// 	.environment(Environment::Sway)
+	/// 	.runner(Hanging)
+	/// 	.timeout(Duration::from_millis(100))
+	/// 	.build();
+	/// assert!(matches!(result, Err(WallpaperError::Timeout { .. })));
+	/// ```
+	#[cfg(target_os = "linux")]
+	pub fn timeout(mut self, timeout: Duration) -> Self {
+		self.timeout = Some(timeout);
+		self
+	}
+
+	///Use the given [`CancelToken`] to cancel the reading and setting of the wallpapers.
+	pub fn cancel_token(mut self, token: CancelToken) -> Self {
+		self.cancel = token;
+		self
+	}
+
+	///Read the [focal point](Screen::focal_point) of cropped wallpapers from their [metadata](FocalPoint::from_metadata),
+	///if no focal point is set. The default is `false`.
+	///A focal point requires pre-rendering the wallpaper with [ImageMagick](https://imagemagick.org).
+	#[cfg(target_os = "linux")]
+	pub fn focal_point_metadata(mut self, enable: bool) -> Self {
+		self.focal_point_metadata = enable;
+		self
+	}
+
+	///Create the [`WallpaperBuilder`].
+	pub fn build(self) -> Result<WallpaperBuilder, WallpaperError> {
+		let environment = match self.environment {
+			Some(environment) => environment,
+			None => get_environment()?,
+		};
+		let session = Session {
+			#[cfg(target_os = "linux")]
+			runner: self.runner.unwrap_or_else(|| Arc::new(DefaultRunner)),
+			#[cfg(target_os = "linux")]
+			timeout: self.timeout.unwrap_or(DEFAULT_TIMEOUT),
+			cancel: self.cancel,
+			#[cfg(target_os = "linux")]
+			focal_point_metadata: self.focal_point_metadata,
+		};
+		get_builder(environment, session)
+	}
 }
 
 impl WallpaperBuilder {
 	pub fn new() -> Result<Self, WallpaperError> {
-		get_builder()
+		Self::options().build()
+	}
+
+	///Like [`Self::new()`], but use the given environment instead of detecting it.
+	///This is useful if the environment can not be detected, like inside cron jobs or ssh sessions.
+	///```no_run
+	/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+	/// use more_wallpapers::{Mode, WallpaperBuilder};
+	///
+	/// WallpaperBuilder::with_environment("sway".parse()?)?
+	/// 	.set_wallpapers(|_| ("1.jpg", Mode::Crop))?;
+	/// # Ok(())}
+	/// ```
+	pub fn with_environment(environment: Environment) -> Result<Self, WallpaperError> {
+		Self::options().environment(environment).build()
+	}
+
+	///Return [`BuilderOptions`] for more control about creating the builder.
+	pub fn options() -> BuilderOptions {
+		BuilderOptions::default()
+	}
+
+	pub(crate) fn from_screens(environment: Environment, screens: Vec<Screen>, session: Session) -> Self {
+		let changed = vec![false; screens.len()];
+		Self {
+			screens,
+			environment,
+			changed,
+			session,
+		}
 	}
 
 	///Return the count of active screens. This does not include disable screens.
@@ -278,53 +707,323 @@ impl WallpaperBuilder {
 		&self.screens
 	}
 
+	///Return a [`WallpaperSnapshot`] of the current state of all screens.
+	pub fn snapshot(&self) -> WallpaperSnapshot {
+		WallpaperSnapshot {
+			environment: self.environment,
+			screens: self.screens.clone(),
+		}
+	}
+
+	///Return the indices of all active screens.
+	///The primary screen comes first, followed by the other screens from left to right.
+	///Screens with unknown position are placed at the end.
+	fn active_screen_order(&self) -> Vec<usize> {
+		let mut order: Vec<usize> = (0..self.screens.len()).filter(|i| self.screens[*i].active).collect();
+		order.sort_by_key(|i| {
+			let screen = &self.screens[*i];
+			let position = screen
+				.geometry
+				.map(|geometry| (geometry.x, geometry.y))
+				.unwrap_or((i32::MAX, i32::MAX));
+			(!screen.primary, position)
+		});
+		order
+	}
+
+	///Set the color witch fills the empty space left by [`Mode::Fit`] and [`Mode::Center`].
+	///The color is selected per screen by the given closure.
+	///Returning `None` keeps the current color.
+	///This must be called before the wallpapers are set.
+	///If the environment does not support fill colors, the wallpaper is pre-rendered with the fill color.
+	///This requires a known [screen size](Screen::geometry), otherwise the fill color is ignored.
+	///```no_run
+	/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+	/// use more_wallpapers::{Color, Mode, WallpaperBuilder};
+	///
+	/// WallpaperBuilder::new()?
+	/// 	.with_fill_color(|_| Some(Color::new(0, 43, 54)))
+	/// 	.set_wallpapers(|_| ("portrait.jpg", Mode::Fit))?;
+	/// # Ok(())}
+	/// ```
+	pub fn with_fill_color<F>(mut self, mut f: F) -> Self
+	where
+		F: FnMut(&Screen) -> Option<Color>,
+	{
+		for screen in self.screens.iter_mut() {
+			if let Some(color) = f(screen) {
+				screen.fill_color = Some(color);
+			}
+		}
+		self
+	}
+
+	///Set the [focal point](FocalPoint) of the wallpaper for each screen.
+	///If a focal point is set, the wallpaper is pre-rendered to match the size of the screen,
+	///instead of cropping around the center of the image.
+	///Returning `None` keeps the current focal point.
+	///This must be called before the wallpapers are set.
+	pub fn with_focal_point<F>(mut self, mut f: F) -> Self
+	where
+		F: FnMut(&Screen) -> Option<FocalPoint>,
+	{
+		for screen in self.screens.iter_mut() {
+			if let Some(focal_point) = f(screen) {
+				screen.focal_point = Some(focal_point);
+			}
+		}
+		self
+	}
+
 	///Set background to wallpapers, witch will be selected by the given closure.
 	///The index oft screen and the current screen are passed to the closure.x
 	#[doc = doc_WallpaperBuilder_example!()]
-	pub fn set_wallpapers<F, P>(mut self, mut f: F) -> Result<(), WallpaperError>
+	pub fn set_wallpapers<F, P>(self, mut f: F) -> Result<(), WallpaperError>
 	where
 		P: AsRef<Utf8Path>,
 		F: FnMut(&Screen) -> (P, Mode),
 	{
-		for screen in self.screens.iter_mut() {
-			let tuple = f(screen);
-			let path = tuple.0.as_ref();
-			let path = path.canonicalize_utf8().context(path)?;
-			if !path.exists() {
-				return Err(io::Error::from(io::ErrorKind::NotFound)).context(path);
+		self.set_backgrounds(|screen| {
+			let (path, mode) = f(screen);
+			(path.as_ref().to_owned(), mode)
+		})
+	}
+
+	///Like [`Self::set_wallpapers`], but the closure can fail.
+	///The error of the closure is returned as [`WallpaperError::Selection`].
+	///If the closure fails for any screen, no wallpaper is changed.
+	///```no_run
+	/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+	/// use more_wallpapers::{Mode, WallpaperBuilder};
+	/// use std::fs;
+	///
+	/// WallpaperBuilder::new()?.try_set_wallpapers(|screen| {
+	/// 	let wallpaper = fs::read_to_string(format!("wallpapers/{}.txt", screen.name))?;
+	/// 	Ok::<_, std::io::Error>((wallpaper.trim().to_owned(), Mode::Crop))
+	/// })?;
+	/// # Ok(())}
+	/// ```
+	pub fn try_set_wallpapers<F, P, E>(self, mut f: F) -> Result<(), WallpaperError>
+	where
+		P: AsRef<Utf8Path>,
+		F: FnMut(&Screen) -> Result<(P, Mode), E>,
+		E: Into<Box<dyn Error + Send + Sync>>,
+	{
+		self.try_update_backgrounds(|screen| {
+			let (path, mode) = f(screen)?;
+			Ok::<_, E>(Some((path.as_ref().to_owned(), mode)))
+		})
+	}
+
+	///Like [`Self::set_wallpapers`], but the closure can also select a [`Background`],
+	///witch is not an image, like a single color or a gradient.
+	///```no_run
+	/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+	/// use more_wallpapers::{Background, Color, GradientDirection, Mode, WallpaperBuilder};
+	///
+	/// WallpaperBuilder::new()?.set_backgrounds(|screen| {
+	/// 	if screen.primary {
+	/// 		return (Background::Color(Color::new(0, 43, 54)), Mode::default());
+	/// 	}
+	/// 	let gradient = Background::Gradient {
+	/// 		direction: GradientDirection::Vertical,
+	/// 		start: Color::BLACK,
+	/// 		end: "#268bd2".parse().unwrap(),
+	/// 	};
+	/// 	(gradient, Mode::default())
+	/// })?;
+	/// # Ok(())}
+	/// ```
+	pub fn set_backgrounds<F, B>(self, mut f: F) -> Result<(), WallpaperError>
+	where
+		B: Into<Background>,
+		F: FnMut(&Screen) -> (B, Mode),
+	{
+		self.update_backgrounds(|screen| Some(f(screen)))
+	}
+
+	///Like [`Self::set_wallpapers`], but returning `None` keeps the current wallpaper of the screen.
+	///Only the screens with a new wallpaper are changed.
+	///```no_run
+	/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+	/// use more_wallpapers::{Mode, WallpaperBuilder};
+	///
+	/// WallpaperBuilder::new()?.update_wallpapers(|screen| {
+	/// 	if screen.name == "HDMI1" {
+	/// 		Some(("/usr/share/wallpapers/1.jpg", Mode::Crop))
+	/// 	} else {
+	/// 		None
+	/// 	}
+	/// })?;
+	/// # Ok(())}
+	/// ```
+	pub fn update_wallpapers<F, P>(self, mut f: F) -> Result<(), WallpaperError>
+	where
+		P: AsRef<Utf8Path>,
+		F: FnMut(&Screen) -> Option<(P, Mode)>,
+	{
+		self.update_backgrounds(|screen| f(screen).map(|(path, mode)| (path.as_ref().to_owned(), mode)))
+	}
+
+	///Like [`Self::set_backgrounds`], but returning `None` keeps the current background of the screen.
+	///Only the screens with a new background are changed.
+	pub fn update_backgrounds<F, B>(self, mut f: F) -> Result<(), WallpaperError>
+	where
+		B: Into<Background>,
+		F: FnMut(&Screen) -> Option<(B, Mode)>,
+	{
+		self.try_update_backgrounds(|screen| Ok::<_, Infallible>(f(screen)))
+	}
+
+	fn try_update_backgrounds<F, B, E>(mut self, f: F) -> Result<(), WallpaperError>
+	where
+		B: Into<Background>,
+		F: FnMut(&Screen) -> Result<Option<(B, Mode)>, E>,
+		E: Into<Box<dyn Error + Send + Sync>>,
+	{
+		self.try_select_backgrounds(f)?;
+		self.apply_changes()
+	}
+
+	///Set the backgrounds selected by the closure, without applying them.
+	fn try_select_backgrounds<F, B, E>(&mut self, mut f: F) -> Result<(), WallpaperError>
+	where
+		B: Into<Background>,
+		F: FnMut(&Screen) -> Result<Option<(B, Mode)>, E>,
+		E: Into<Box<dyn Error + Send + Sync>>,
+	{
+		for mut screen in self.screens_mut() {
+			let Some((background, mode)) = f(&screen).map_err(|error| WallpaperError::Selection(error.into()))? else {
+				continue;
+			};
+			screen.set_background(background, mode);
+		}
+		Ok(())
+	}
+
+	///Return the screen with the given name for modification.
+	///The changes are applied by [`Self::apply()`].
+	///```no_run
+	/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+	/// use more_wallpapers::{Mode, WallpaperBuilder};
+	///
+	/// let mut builder = WallpaperBuilder::new()?;
+	/// builder
+	/// 	.screen_mut("DP-1")?
+	/// 	.set("/usr/share/wallpapers/1.jpg", Mode::Crop);
+	/// builder.apply()?;
+	/// # Ok(())}
+	/// ```
+	pub fn screen_mut(&mut self, name: &str) -> Result<ScreenMut<'_>, WallpaperError> {
+		self.screens_mut()
+			.find(|screen| screen.name == name)
+			.ok_or_else(|| WallpaperError::UnknownScreen(name.to_owned()))
+	}
+
+	///Return all screens for modification.
+	///The changes are applied by [`Self::apply()`].
+	pub fn screens_mut(&mut self) -> impl Iterator<Item = ScreenMut<'_>> {
+		self.screens
+			.iter_mut()
+			.zip(self.changed.iter_mut())
+			.map(|(screen, changed)| ScreenMut { screen, changed })
+	}
+
+	///Apply the changes made by [`Self::screen_mut()`] and [`Self::screens_mut()`].
+	///Only the modified screens are changed.
+	///
+	///Return [`WallpaperError::MissingWallpaper`], if a active screen has neither a current nor a new wallpaper.
+	pub fn apply(self) -> Result<(), WallpaperError> {
+		self.check_active_screens()?;
+		self.apply_changes()
+	}
+
+	///Like [`Self::apply()`], but return the operations, witch would be performed, without changing the wallpapers.
+	///Images, witch must be pre-rendered, are already created.
+	///Registered backends must implement [`Backend::plan()`], otherwise [`WallpaperError::Unsuported`] is returned.
+	///```no_run
+	/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+	/// use more_wallpapers::{Mode, WallpaperBuilder};
+	///
+	/// let mut builder = WallpaperBuilder::new()?;
+	/// for mut screen in builder.screens_mut() {
+	/// 	screen.set("/usr/share/wallpapers/1.jpg", Mode::Crop);
+	/// }
+	/// let plan = builder.plan()?;
+	/// println!("{plan}");
+	/// plan.apply()?;
+	/// # Ok(())}
+	/// ```
+	pub fn plan(self) -> Result<Plan, WallpaperError> {
+		self.check_active_screens()?;
+		plan::plan(self)
+	}
+
+	///Return [`WallpaperError::MissingWallpaper`], if a active screen has neither a current nor a new wallpaper.
+	fn check_active_screens(&self) -> Result<(), WallpaperError> {
+		match self.screens.iter().find(|screen| screen.active && !screen.is_set()) {
+			Some(screen) => Err(WallpaperError::MissingWallpaper(screen.name.clone())),
+			None => Ok(()),
+		}
+	}
+
+	///Check the changed screens and pass them to the environment.
+	fn apply_changes(mut self) -> Result<(), WallpaperError> {
+		for (screen, _) in self.screens.iter_mut().zip(&self.changed).filter(|(_, changed)| **changed) {
+			if !screen.is_set() {
+				return Err(WallpaperError::MissingWallpaper(screen.name.clone()));
+			}
+			if let Some(Background::Image(path)) = &mut screen.background {
+				*path = path.canonicalize_utf8().context(&path)?;
+				if !path.exists() {
+					return Err(io::Error::from(io::ErrorKind::NotFound)).context(path);
+				}
 			}
-			screen.wallpaper = Some(path);
-			screen.mode = Some(tuple.1)
 		}
 		set_screens_from_builder(self)
 	}
 
 	#[doc = doc_set_wallpapers_from_vec!(builder)]
 	pub fn set_wallpapers_from_vec<P>(
-		self,
+		mut self,
 		wallpapers: Vec<P>,
 		default_wallpaper: P,
 		mode: Mode,
 	) -> Result<Vec<Utf8PathBuf>, WallpaperError>
+	where
+		P: AsRef<Utf8Path>,
+	{
+		let used_wallpapers = self.select_wallpapers_from_vec(wallpapers, default_wallpaper, mode);
+		self.apply_changes()?;
+		Ok(used_wallpapers)
+	}
+
+	///Like [`Self::set_wallpapers_from_vec`], but only select the wallpapers.
+	///The changes can be applied by [`Self::apply()`] or checked by [`Self::plan()`].
+	pub fn select_wallpapers_from_vec<P>(&mut self, wallpapers: Vec<P>, default_wallpaper: P, mode: Mode) -> Vec<Utf8PathBuf>
 	where
 		P: AsRef<Utf8Path>,
 	{
 		let mut used_wallpapers = Vec::new();
-		let mut i = 0;
-		self.set_wallpapers(|screen| {
-			if !screen.active {
-				return (default_wallpaper.as_ref(), mode);
-			}
+		let mut assigned_wallpapers: Vec<Option<&Utf8Path>> = vec![None; self.screens.len()];
+		for (i, index) in self.active_screen_order().into_iter().enumerate() {
 			let wallpaper = if wallpapers.is_empty() {
 				default_wallpaper.as_ref()
 			} else {
 				wallpapers[i % wallpapers.len()].as_ref()
 			};
-			i += 1;
 			used_wallpapers.push(wallpaper.to_owned());
-			(wallpaper, mode)
-		})?;
-		Ok(used_wallpapers)
+			assigned_wallpapers[index] = Some(wallpaper);
+		}
+		let assigned_wallpapers: Vec<Utf8PathBuf> = assigned_wallpapers
+			.into_iter()
+			// inactive screens have no assigned wallpaper
+			.map(|wallpaper| wallpaper.unwrap_or(default_wallpaper.as_ref()).to_owned())
+			.collect();
+		for (mut screen, wallpaper) in self.screens_mut().zip(assigned_wallpapers) {
+			screen.set(wallpaper, mode);
+		}
+		used_wallpapers
 	}
 
 	///Like [`Self::set_wallpapers_from_vec`],
@@ -340,10 +1039,20 @@ impl WallpaperBuilder {
 	where
 		P: AsRef<Utf8Path>,
 		P: Clone,
+	{
+		let wallpapers = self.shuffle_wallpapers(wallpapers);
+		self.set_wallpapers_from_vec(wallpapers, default_wallpaper, mode)
+	}
+
+	///Return the wallpapers in random order, repeated if there are less wallpapers than screens.
+	#[cfg(feature = "rand")]
+	fn shuffle_wallpapers<P>(&self, wallpapers: Vec<P>) -> Vec<P>
+	where
+		P: Clone,
 	{
 		if wallpapers.is_empty() {
 			// set_wallpapers_from_vec() will deal the empty inupt
-			return self.set_wallpapers_from_vec(wallpapers, default_wallpaper, mode);
+			return wallpapers;
 		}
 		let mut rng = rand::thread_rng();
 		let wallpapers = if wallpapers.len() < self.screen_count() {
@@ -360,7 +1069,7 @@ impl WallpaperBuilder {
 		};
 		let mut choose_wallpapers = wallpapers.into_iter().choose_multiple(&mut rng, self.screen_count());
 		choose_wallpapers.shuffle(&mut rng);
-		self.set_wallpapers_from_vec(choose_wallpapers, default_wallpaper, mode)
+		choose_wallpapers
 	}
 }
 
@@ -393,3 +1102,215 @@ where
 	let builder = WallpaperBuilder::new()?;
 	builder.set_random_wallpapers_from_vec(wallpapers, default_wallpaper, mode)
 }
+
+///Return all missing runtime dependencies of the detected environment.
+///See [`Environment::check_requirements()`].
+///```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// for missing in more_wallpapers::check_requirements()? {
+/// 	eprintln!("{missing}");
+/// }
+/// # Ok(())}
+/// ```
+pub fn check_requirements() -> Result<Vec<MissingRequirement>, WallpaperError> {
+	Ok(get_environment()?.check_requirements())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// backend, witch plans to set the file name of the wallpaper of each changed screen
+	struct Recorder;
+
+	impl Backend for Recorder {
+		fn name(&self) -> &'static str {
+			"recorder"
+		}
+
+		fn detect(&self) -> bool {
+			false
+		}
+
+		fn get_screens(&self, _session: &Session) -> Result<Vec<Screen>, WallpaperError> {
+			Ok(Vec::new())
+		}
+
+		fn set_screens(&self, _session: &Session, _screens: Vec<Screen>, _changed: &[bool]) -> Result<(), WallpaperError> {
+			panic!("wallpapers were set at a test");
+		}
+
+		fn plan(
+			&self,
+			_session: &Session,
+			screens: Vec<Screen>,
+			changed: &[bool],
+		) -> Result<Vec<Operation>, WallpaperError> {
+			Ok(screens
+				.iter()
+				.zip(changed)
+				.filter(|(_, changed)| **changed)
+				.map(|(screen, _)| Operation::Command {
+					program: screen.name.clone(),
+					args: vec![screen
+						.wallpaper()
+						.and_then(Utf8Path::file_name)
+						.unwrap_or_default()
+						.to_owned()],
+				})
+				.collect())
+		}
+
+		fn capabilities(&self) -> Capabilities {
+			Capabilities {
+				various_wallpaper: true,
+				modes: &[Mode::Crop],
+				..Default::default()
+			}
+		}
+	}
+
+	fn screen(name: &str, primary: bool, x: Option<i32>) -> Screen {
+		Screen {
+			name: name.to_owned(),
+			background: None,
+			mode: None,
+			fill_color: None,
+			focal_point: None,
+			active: true,
+			primary,
+			geometry: x.map(|x| Rectangle {
+				x,
+				y: 0,
+				width: 1920,
+				height: 1080,
+			}),
+		}
+	}
+
+	/// existing file, witch can be used as wallpaper
+	fn wallpaper(name: &str) -> Utf8PathBuf {
+		Utf8Path::new(env!("CARGO_MANIFEST_DIR")).join(name)
+	}
+
+	/// builder of the recorder backend with the screens `DP-1`, `DP-2` and `HDMI-1`
+	fn recorder_builder() -> WallpaperBuilder {
+		register_backend(Recorder, i32::MIN);
+		let mut screens = vec![
+			screen("DP-1", false, None),
+			screen("DP-2", false, None),
+			screen("HDMI-1", false, None),
+		];
+		for screen in &mut screens {
+			screen.background = Some(Background::Image(wallpaper("Cargo.toml")));
+			screen.mode = Some(Mode::Crop);
+		}
+		WallpaperBuilder::from_screens(Environment::Custom("recorder"), screens, Session::default())
+	}
+
+	/// return the screens and wallpapers, witch would be set by `f`
+	fn planned<F>(f: F) -> Result<Vec<(String, String)>, WallpaperError>
+	where
+		F: FnOnce() -> Result<(), WallpaperError>,
+	{
+		let (operations, result) = plan::recording(f);
+		result?;
+		Ok(operations
+			.into_iter()
+			.map(|operation| match operation {
+				Operation::Command { program, mut args } => (program, args.remove(0)),
+				operation => panic!("unexpected operation {operation}"),
+			})
+			.collect())
+	}
+
+	fn pair(screen: &str, wallpaper: &str) -> (String, String) {
+		(screen.to_owned(), wallpaper.to_owned())
+	}
+
+	#[test]
+	fn primary_screen_first_then_left_to_right() {
+		let mut inactive = screen("inactive", false, Some(-1920));
+		inactive.active = false;
+		let screens = vec![
+			screen("unknown", false, None),
+			screen("right", false, Some(1920)),
+			inactive,
+			screen("primary", true, Some(3840)),
+			screen("left", false, Some(0)),
+		];
+		let builder = WallpaperBuilder::from_screens(Environment::Custom("recorder"), screens, Session::default());
+		assert_eq!(builder.active_screen_order(), [3, 4, 1, 0]);
+	}
+
+	#[test]
+	fn wallpapers_from_vec_follow_the_screen_order() {
+		let mut builder = recorder_builder();
+		builder.screens[2].primary = true;
+		let used = builder.select_wallpapers_from_vec(vec!["1.jpg", "2.jpg"], "default.jpg", Mode::Crop);
+		assert_eq!(used, ["1.jpg", "2.jpg", "1.jpg"]);
+		let wallpapers: Vec<_> = builder.screens().iter().map(|screen| screen.wallpaper().unwrap()).collect();
+		assert_eq!(wallpapers, ["2.jpg", "1.jpg", "1.jpg"]);
+		assert_eq!(builder.changed, [true, true, true]);
+	}
+
+	#[test]
+	fn update_wallpapers_changes_only_the_selected_screens() {
+		let builder = recorder_builder();
+		let result = planned(|| {
+			builder.update_wallpapers(|screen| (screen.name == "DP-2").then(|| (wallpaper("src/lib.rs"), Mode::Crop)))
+		});
+		assert_eq!(result.unwrap(), [pair("DP-2", "lib.rs")]);
+	}
+
+	#[test]
+	fn try_set_wallpapers_changes_nothing_on_error() {
+		let builder = recorder_builder();
+		let result = planned(|| {
+			builder.try_set_wallpapers(|screen| match screen.name.as_str() {
+				"HDMI-1" => Err("no wallpaper for HDMI-1"),
+				_ => Ok((wallpaper("src/lib.rs"), Mode::Crop)),
+			})
+		});
+		assert!(matches!(result, Err(WallpaperError::Selection(_))));
+
+		let builder = recorder_builder();
+		let result = planned(|| builder.try_set_wallpapers(|_| Ok::<_, io::Error>((wallpaper("src/lib.rs"), Mode::Crop))));
+		assert_eq!(result.unwrap(), [
+			pair("DP-1", "lib.rs"),
+			pair("DP-2", "lib.rs"),
+			pair("HDMI-1", "lib.rs")
+		]);
+	}
+
+	#[test]
+	fn screen_mut_marks_only_the_screen_as_changed() {
+		let mut builder = recorder_builder();
+		assert!(matches!(builder.screen_mut("DP-3"), Err(WallpaperError::UnknownScreen(_))));
+		builder.screen_mut("HDMI-1").unwrap().set(wallpaper("src/lib.rs"), Mode::Crop);
+		assert_eq!(builder.changed, [false, false, true]);
+		let result = planned(|| builder.apply());
+		assert_eq!(result.unwrap(), [pair("HDMI-1", "lib.rs")]);
+	}
+
+	#[test]
+	fn environment_from_str() {
+		#[cfg(target_os = "linux")]
+		{
+			assert_eq!("sway".parse(), Ok(Environment::Sway));
+			assert_eq!("KDE".parse(), Ok(Environment::Kde));
+			assert_eq!(Environment::try_from("x11".to_owned()), Ok(Environment::X11));
+		}
+		#[cfg(all(target_os = "linux", feature = "fallback"))]
+		assert_eq!("linuxfallback".parse(), Ok(Environment::LinuxFallback));
+		assert!("gnome".parse::<Environment>().is_err());
+		// custom environments are only known, if they are registered
+		assert!("recorder".parse::<Environment>().is_err());
+		register_backend(Recorder, i32::MIN);
+		assert_eq!(
+			Environment::try_from("recorder".to_owned()),
+			Ok(Environment::Custom("recorder"))
+		);
+		assert!(Environment::try_from("unregistered".to_owned()).is_err());
+	}
+}
@@ -1,10 +1,11 @@
 use crate::{
-	error::{CommandError, WallpaperError},
-	linux::{run, x11},
-	Mode, Screen,
+	backend::Session,
+	error::WallpaperError,
+	linux::{apply_command, run, utf8_output, x11},
+	plan, Background, GradientDirection, Mode, Screen,
 };
-use camino::Utf8PathBuf;
 use std::{
+	process::Command,
 	str::FromStr,
 	time::{Duration, Instant},
 };
@@ -27,8 +28,9 @@ impl From<Mode> for CMode {
 			Mode::Crop => Self::Zoom,
 			Mode::Center => Self::Centered,
 			Mode::Tile => Self::Wallpaper,
-			Mode::Fit => Self::Scaled,
+			Mode::BlurFill | Mode::Fit => Self::Scaled,
 			Mode::Stretch => Self::Stretched,
+			Mode::Span => Self::Spanned,
 		}
 	}
 }
@@ -41,7 +43,7 @@ impl From<CMode> for Option<Mode> {
 			CMode::Wallpaper => Some(Mode::Tile),
 			CMode::Scaled => Some(Mode::Fit),
 			CMode::Stretched => Some(Mode::Stretch),
-			CMode::Spanned => None,
+			CMode::Spanned => Some(Mode::Span),
 		}
 	}
 }
@@ -49,67 +51,147 @@ impl From<CMode> for Option<Mode> {
 /// This is a wrapper around x11, because cinnamon does support to set different wallpaper per screen; see https://github.com/linuxmint/cinnamon/issues/2301
 /// The wrapper add the abbility to set the default screen.
 
-fn get_delault_screen() -> Result<Screen, CommandError> {
-	let output = run("dconf", ["read", "/org/cinnamon/desktop/background/picture-uri"])?;
-	let default_wallpaper: Option<Utf8PathBuf> = String::from_utf8(output)
-		.ok()
-		.map(|wallpaper| wallpaper.trim_start_matches("'file://").trim_end_matches("'\n").into());
-	let output = run("dconf", ["read", "/org/cinnamon/desktop/background/picture-options"])?;
-	let default_mode = String::from_utf8(output)
-		.ok()
-		.map(|value| {
-			CMode::from_str(&value.trim_start_matches("'").trim_end_matches("'\n"))
-				.ok()
-				.map(|value| Option::<Mode>::from(value))
-				.flatten()
-		})
-		.flatten();
+/// read a string value from the cinnamon background settings
+fn read(session: &Session, key: &str) -> Result<Option<String>, WallpaperError> {
+	let output = run(session, "dconf", ["read", &format!("/org/cinnamon/desktop/background/{key}")])?;
+	parse_value(output)
+}
+
+/// parse a string value printed by `dconf read`, like `'zoom'`
+fn parse_value(output: Vec<u8>) -> Result<Option<String>, WallpaperError> {
+	let value = utf8_output("dconf", output)?;
+	Ok(Some(value.trim_end_matches('\n').trim_matches('\'').to_owned()).filter(|value| !value.is_empty()))
+}
+
+/// write a string value to the cinnamon background settings
+fn write(session: &Session, key: &str, value: &str) -> Result<(), WallpaperError> {
+	let mut command = Command::new("dconf");
+	command
+		.arg("write")
+		.arg(format!("/org/cinnamon/desktop/background/{key}"))
+		.arg(format!("'{value}'"));
+	apply_command(session, command)?;
+	Ok(())
+}
+
+fn get_delault_screen(session: &Session) -> Result<Screen, WallpaperError> {
+	let options = read(session, "picture-options")?;
+	let (background, mode, fill_color) = if options.as_deref() == Some("none") {
+		//single color background is used instead of a image
+		let start = read(session, "primary-color")?.and_then(|color| color.parse().ok());
+		let end = read(session, "secondary-color")?.and_then(|color| color.parse().ok());
+		let direction = match read(session, "color-shading-type")?.as_deref() {
+			Some("horizontal") => Some(GradientDirection::Horizontal),
+			Some("vertical") => Some(GradientDirection::Vertical),
+			_ => None,
+		};
+		(Background::from_colors(direction, start, end), None, None)
+	} else {
+		let wallpaper =
+			read(session, "picture-uri")?.map(|wallpaper| Background::Image(wallpaper.trim_start_matches("file://").into()));
+		let mode = options
+			.and_then(|value| CMode::from_str(&value).ok())
+			.and_then(Option::<Mode>::from);
+		let fill_color = match read(session, "color-shading-type")?.as_deref() {
+			Some("solid") => read(session, "primary-color")?.and_then(|color| color.parse().ok()),
+			_ => None,
+		};
+		(wallpaper, mode, fill_color)
+	};
 	let screen = Screen {
 		name: "default".to_owned(),
-		wallpaper: default_wallpaper,
-		mode: default_mode,
+		background,
+		mode,
+		fill_color,
+		focal_point: None,
 		active: false,
+		primary: false,
+		geometry: None,
 	};
 	Ok(screen)
 }
 
-pub(crate) fn get_screens() -> Result<Vec<Screen>, WallpaperError> {
-	let mut screens = vec![get_delault_screen()?];
+pub(crate) fn get_screens(session: &Session) -> Result<Vec<Screen>, WallpaperError> {
+	let mut screens = vec![get_delault_screen(session)?];
 	screens.append(&mut x11::get_screens()?);
 	Ok(screens)
 }
 
-pub(crate) fn set_screens(screens: Vec<Screen>) -> Result<(), WallpaperError> {
+pub(crate) fn set_screens(session: &Session, screens: Vec<Screen>) -> Result<(), WallpaperError> {
 	let (x11_screens, default_screen): (Vec<Screen>, Vec<Screen>) = screens.into_iter().partition(|screen| screen.active);
-	x11::set_screens(x11_screens.clone())?;
+	x11::set_screens(session, x11_screens.clone())?;
 	let mut changed = false;
 	if let Some(screen) = default_screen.first() {
-		let current_state = get_delault_screen()?;
-		if screen.mode != current_state.mode {
+		let current_state = get_delault_screen(session)?;
+		let background = screen.require_background()?;
+		if screen.background != current_state.background
+			|| (background.image().is_some() && screen.mode != current_state.mode)
+			|| (screen.fill_color.is_some() && screen.fill_color != current_state.fill_color)
+		{
 			changed = true;
-			run("dconf", [
-				"write",
-				"/org/cinnamon/desktop/background/picture-options",
-				&format!("'{}'", CMode::from(screen.mode.unwrap())),
-			])?;
-		}
-		if screen.wallpaper != current_state.wallpaper {
-			changed = true;
-			run("dconf", [
-				"write",
-				"/org/cinnamon/desktop/background/picture-uri",
-				&format!("'file://{}'", screen.wallpaper.as_ref().unwrap()),
-			])?;
+			match background {
+				Background::Image(wallpaper) => {
+					write(session, "picture-options", &CMode::from(screen.require_mode()?).to_string())?;
+					write(session, "picture-uri", &format!("file://{wallpaper}"))?;
+					if let Some(color) = screen.fill_color {
+						write(session, "primary-color", &color.to_string())?;
+						write(session, "color-shading-type", "solid")?;
+					}
+				},
+				Background::Color(color) => {
+					write(session, "picture-options", "none")?;
+					write(session, "primary-color", &color.to_string())?;
+					write(session, "color-shading-type", "solid")?;
+				},
+				Background::Gradient { direction, start, end } => {
+					let shading = match direction {
+						GradientDirection::Horizontal => "horizontal",
+						GradientDirection::Vertical => "vertical",
+					};
+					write(session, "picture-options", "none")?;
+					write(session, "primary-color", &start.to_string())?;
+					write(session, "secondary-color", &end.to_string())?;
+					write(session, "color-shading-type", shading)?;
+				},
+			}
 		}
 	}
 	//need to set wallpaper multiple times, otherwise cinnamon does override them.
 	//I do not like this solution, but it does work.
 	//at least the time is constant and is independent from the cinnamon effect speed
-	if changed {
+	if changed && !plan::is_planning() {
 		let time = Instant::now();
 		while time.elapsed() < Duration::from_millis(900) {
-			x11::set_screens(x11_screens.clone())?;
+			x11::set_screens(session, x11_screens.clone())?;
 		}
 	}
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::linux::runner::tests::scripted;
+
+	#[test]
+	fn parse_value() {
+		assert_eq!(super::parse_value(b"'zoom'\n".to_vec()).unwrap().as_deref(), Some("zoom"));
+		assert_eq!(super::parse_value(b"\n".to_vec()).unwrap(), None);
+		assert_eq!(super::parse_value(Vec::new()).unwrap(), None);
+		assert!(matches!(
+			super::parse_value(b"\xff\xfe".to_vec()),
+			Err(WallpaperError::InvalidOutput { .. })
+		));
+	}
+
+	#[test]
+	fn malformed_default_screen() {
+		// every key returns the same value, witch is neither a known mode nor a color
+		let screen = get_delault_screen(&scripted(b"'foo'\n")).unwrap();
+		assert_eq!(screen.background, Some(Background::Image("foo".into())));
+		assert_eq!(screen.mode, None);
+		assert_eq!(screen.fill_color, None);
+		let result = get_delault_screen(&scripted(b"\xff\xfe"));
+		assert!(matches!(result, Err(WallpaperError::InvalidOutput { .. })));
+	}
+}
@@ -0,0 +1,313 @@
+use crate::{backend, Environment};
+use std::{env, fmt, fs, os::unix::fs::MetadataExt};
+
+/// environment variables, witch are considered by the detection
+const VARIABLES: &[&str] = &[
+	"MORE_WALLPAPERS_ENVIRONMENT",
+	"SWAYSOCK",
+	"HYPRLAND_INSTANCE_SIGNATURE",
+	"XDG_CURRENT_DESKTOP",
+	"DESKTOP_SESSION",
+	"XDG_SESSION_TYPE",
+	"WAYLAND_DISPLAY",
+	"DISPLAY",
+];
+
+/// processes, witch indicate a running environment
+const PROCESSES: &[(&str, Environment)] = &[
+	("cinnamon", Environment::Cinnamon),
+	("plasmashell", Environment::Kde),
+	("sway", Environment::Sway),
+	("xfdesktop", Environment::Xfce),
+];
+
+/// Result of the environment detection, including the signals seen and the reason for the chosen environment.
+/// ```
+/// use more_wallpapers::DetectionReport;
+///
+/// let report = DetectionReport::detect();
+/// println!("{report}");
+/// ```
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct DetectionReport {
+	/// considered environment variables and their value
+	pub variables: Vec<(&'static str, Option<String>)>,
+	/// running processes, witch indicate a environment
+	pub processes: Vec<String>,
+	/// detected environment, `None` if no supported environment was found
+	pub environment: Option<Environment>,
+	/// why the environment was chosen or why detection has failed
+	pub reason: String,
+}
+
+impl DetectionReport {
+	/// Detect the environment from the environment variables and the running processes.
+	/// The processes are only used, if the environment variables are not sufficient.
+	/// [Registered backends](crate::register_backend) are detected before or after the built-in environments
+	/// depending on their priority, unless the environment is selected by `MORE_WALLPAPERS_ENVIRONMENT`.
+	#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug"))]
+	pub fn detect() -> Self {
+		let var = |key: &str| env::var(key).ok();
+		let overridden = var("MORE_WALLPAPERS_ENVIRONMENT").map_or(false, |value| !value.is_empty());
+		let registered = |before_builtin| if overridden { None } else { backend::detect(before_builtin) };
+		let report = match registered(true) {
+			Some(backend) => {
+				// the built-in environments are not used, so the processes does not need to be scanned
+				Self::from_signals(var, Vec::new).registered(backend.name())
+			},
+			None => {
+				let report = Self::detect_builtin();
+				match report.environment.map_or_else(|| registered(false), |_| None) {
+					Some(backend) => report.registered(backend.name()),
+					None => report,
+				}
+			},
+		};
+		#[cfg(feature = "tracing")]
+		tracing::debug!(environment = ?report.environment, reason = %report.reason, "environment detected");
+		report
+	}
+
+	/// detect only the built-in environments
+	pub(crate) fn detect_builtin() -> Self {
+		Self::from_signals(|key| env::var(key).ok(), running_processes)
+	}
+
+	/// replace the detected environment by the registered backend
+	fn registered(mut self, name: &'static str) -> Self {
+		self.environment = Some(Environment::Custom(name));
+		self.reason = format!("registered backend {name:?} detected");
+		self
+	}
+
+	/// Detect the environment from the given signals.
+	/// `var` returns the value of an environment variable and `processes` lists the names of the running processes.
+	/// `processes` is only called, if the environment variables are not sufficient.
+	pub(crate) fn from_signals<F, P>(var: F, processes: P) -> Self
+	where
+		F: Fn(&str) -> Option<String>,
+		P: FnOnce() -> Vec<String>,
+	{
+		let variables: Vec<(&'static str, Option<String>)> = VARIABLES
+			.iter()
+			.map(|key| (*key, var(key).filter(|value| !value.is_empty())))
+			.collect();
+		let mut seen = Vec::new();
+		let (environment, reason) = detect(&variables, || {
+			seen = processes()
+				.into_iter()
+				.filter(|process| PROCESSES.iter().any(|(name, _)| name == process))
+				.collect();
+			seen.clone()
+		});
+		Self {
+			variables,
+			processes: seen,
+			environment,
+			reason,
+		}
+	}
+}
+
+impl fmt::Display for DetectionReport {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.environment {
+			Some(environment) => writeln!(f, "environment: {environment}")?,
+			None => writeln!(f, "environment: unsupported")?,
+		}
+		writeln!(f, "reason: {}", self.reason)?;
+		for (key, value) in &self.variables {
+			match value {
+				Some(value) => writeln!(f, "{key}={value:?}")?,
+				None => writeln!(f, "{key} is not set")?,
+			}
+		}
+		write!(f, "processes: {:?}", self.processes)
+	}
+}
+
+/// map a desktop name of `XDG_CURRENT_DESKTOP` or `DESKTOP_SESSION` to a environment
+fn environment_from_desktop(desktop: &str) -> Option<Environment> {
+	match desktop {
+		"kde" | "plasma" | "plasmawayland" | "plasmax11" => Some(Environment::Kde),
+		"xfce" | "xfce4" => Some(Environment::Xfce),
+		"x-cinnamon" | "cinnamon" | "cinnamon-wayland" => Some(Environment::Cinnamon),
+		"sway" => Some(Environment::Sway),
+		_ => None,
+	}
+}
+
+fn detect<P>(variables: &[(&'static str, Option<String>)], processes: P) -> (Option<Environment>, String)
+where
+	P: FnOnce() -> Vec<String>,
+{
+	let var = |key: &str| {
+		variables
+			.iter()
+			.find(|(name, _)| *name == key)
+			.and_then(|(_, value)| value.as_deref())
+	};
+	if let Some(value) = var("MORE_WALLPAPERS_ENVIRONMENT") {
+		return match Environment::try_from(value.to_owned()) {
+			Ok(environment) => (Some(environment), "selected by MORE_WALLPAPERS_ENVIRONMENT".to_owned()),
+			Err(_) => (None, format!("unknown environment {value:?} in MORE_WALLPAPERS_ENVIRONMENT")),
+		};
+	}
+	if var("SWAYSOCK").is_some() {
+		return (Some(Environment::Sway), "SWAYSOCK is set".to_owned());
+	}
+	if var("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+		return (None, "hyprland is not supported".to_owned());
+	}
+	// XDG_CURRENT_DESKTOP is a colon separated list, DESKTOP_SESSION can be a path to the session file
+	let desktops: Vec<String> = var("XDG_CURRENT_DESKTOP")
+		.into_iter()
+		.flat_map(|value| value.split(':'))
+		.chain(var("DESKTOP_SESSION").and_then(|value| value.rsplit('/').next()))
+		.map(str::to_lowercase)
+		.filter(|desktop| !desktop.is_empty())
+		.collect();
+	for desktop in &desktops {
+		if let Some(environment) = environment_from_desktop(desktop) {
+			return (Some(environment), format!("desktop {desktop:?} found"));
+		}
+	}
+	let session_type = var("XDG_SESSION_TYPE")
+		.map(str::to_lowercase)
+		.or_else(|| var("WAYLAND_DISPLAY").map(|_| "wayland".to_owned()))
+		.or_else(|| var("DISPLAY").map(|_| "x11".to_owned()));
+	match session_type.as_deref() {
+		Some("x11") => {
+			return (
+				Some(Environment::X11),
+				format!("no known desktop in {desktops:?}, but a x11 session"),
+			)
+		},
+		#[cfg(feature = "fallback")]
+		Some("wayland")
+			if desktops
+				.iter()
+				.any(|desktop| ["budgie", "deepin", "gnome", "lxde", "mate"].contains(&desktop.as_str())) =>
+		{
+			return (
+				Some(Environment::LinuxFallback),
+				format!("desktop {desktops:?} is supported by the fallback"),
+			)
+		},
+		_ => {},
+	}
+	// scanning the processes is slow and less reliable, so they are only used if all variables are not sufficient
+	for process in processes() {
+		if let Some((_, environment)) = PROCESSES.iter().find(|(name, _)| *name == process) {
+			return (Some(*environment), format!("process {process:?} is running"));
+		}
+	}
+	match session_type {
+		Some(session_type) => (None, format!("no supported desktop in {desktops:?} ({session_type} session)")),
+		None => (
+			None,
+			"no desktop variable, running desktop process or display was found".to_owned(),
+		),
+	}
+}
+
+/// Return the names of all running processes of the current user.
+/// Processes of other users, like a second logged in user running a different desktop, are ignored.
+/// The processes are not filtered by session, because desktops started by the systemd user instance
+/// do not belong to the login session.
+fn running_processes() -> Vec<String> {
+	let Ok(uid) = fs::metadata("/proc/self").map(|metadata| metadata.uid()) else {
+		return Vec::new();
+	};
+	let Ok(entries) = fs::read_dir("/proc") else {
+		return Vec::new();
+	};
+	entries
+		.filter_map(Result::ok)
+		.filter(|entry| entry.file_name().to_string_lossy().bytes().all(|byte| byte.is_ascii_digit()))
+		.filter(|entry| entry.metadata().map_or(false, |metadata| metadata.uid() == uid))
+		.filter_map(|entry| fs::read_to_string(entry.path().join("comm")).ok())
+		.map(|comm| comm.trim_end().to_owned())
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// environment variables, running processes and the expected environment
+	type Case = (
+		&'static [(&'static str, &'static str)],
+		&'static [&'static str],
+		Option<Environment>,
+	);
+
+	#[test]
+	fn from_signals() {
+		let cases: &[Case] = &[
+			(&[("XDG_CURRENT_DESKTOP", "KDE")], &[], Some(Environment::Kde)),
+			(&[("XDG_CURRENT_DESKTOP", "KDE:plasma")], &[], Some(Environment::Kde)),
+			(&[("XDG_CURRENT_DESKTOP", "XFCE:xfce4")], &[], Some(Environment::Xfce)),
+			(&[("XDG_CURRENT_DESKTOP", "X-Cinnamon")], &[], Some(Environment::Cinnamon)),
+			(
+				&[("DESKTOP_SESSION", "/usr/share/xsessions/plasma")],
+				&[],
+				Some(Environment::Kde),
+			),
+			(&[("SWAYSOCK", "/run/user/1000/sway-ipc.sock")], &[], Some(Environment::Sway)),
+			(&[("XDG_CURRENT_DESKTOP", "sway")], &[], Some(Environment::Sway)),
+			(
+				&[("XDG_CURRENT_DESKTOP", "ubuntu:GNOME"), ("XDG_SESSION_TYPE", "x11")],
+				&[],
+				Some(Environment::X11),
+			),
+			(&[("XDG_CURRENT_DESKTOP", "i3")], &[], None),
+			(&[("DISPLAY", ":0")], &[], Some(Environment::X11)),
+			(&[("DISPLAY", ":0"), ("WAYLAND_DISPLAY", "wayland-1")], &[], None),
+			(
+				&[("HYPRLAND_INSTANCE_SIGNATURE", "abc"), ("XDG_SESSION_TYPE", "wayland")],
+				&[],
+				None,
+			),
+			(&[], &["systemd", "xfdesktop"], Some(Environment::Xfce)),
+			(&[], &["plasmashell"], Some(Environment::Kde)),
+			// the session type is preferred over the processes
+			(
+				&[("XDG_CURRENT_DESKTOP", "i3"), ("XDG_SESSION_TYPE", "x11")],
+				&["plasmashell"],
+				Some(Environment::X11),
+			),
+			(&[("WAYLAND_DISPLAY", "wayland-1")], &["sway"], Some(Environment::Sway)),
+			(&[], &[], None),
+			(
+				&[("MORE_WALLPAPERS_ENVIRONMENT", "sway"), ("XDG_CURRENT_DESKTOP", "KDE")],
+				&[],
+				Some(Environment::Sway),
+			),
+			(
+				&[("MORE_WALLPAPERS_ENVIRONMENT", "foo"), ("XDG_CURRENT_DESKTOP", "KDE")],
+				&[],
+				None,
+			),
+		];
+		for (variables, processes, environment) in cases {
+			let var = |key: &str| {
+				variables
+					.iter()
+					.find(|(name, _)| *name == key)
+					.map(|(_, value)| value.to_string())
+			};
+			let processes = || processes.iter().map(|process| process.to_string()).collect();
+			let report = DetectionReport::from_signals(var, processes);
+			assert_eq!(report.environment, *environment, "{report}");
+		}
+	}
+
+	#[test]
+	fn processes_are_only_scanned_as_fallback() {
+		let var = |key: &str| (key == "XDG_CURRENT_DESKTOP").then(|| "KDE".to_owned());
+		let report = DetectionReport::from_signals(var, || panic!("processes were scanned"));
+		assert_eq!(report.environment, Some(Environment::Kde));
+	}
+}
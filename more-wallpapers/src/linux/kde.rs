@@ -1,75 +1,346 @@
-use crate::{error::WallpaperError, Mode, Screen};
-use rustbus::{
-	connection,
-	connection::{ll_conn::force_finish_on_error, Timeout},
-	MessageBuilder, RpcConn,
-};
+use super::{render, runner};
+use crate::{backend::Session, error::WallpaperError, Background, Color, DBusCall, Mode, Rectangle, Screen};
+use camino::{Utf8Path, Utf8PathBuf};
 use serde::Deserialize;
-use std::{fmt::Write as _, time::Duration};
+use std::fmt::Write as _;
+
+#[derive(Deserialize)]
+struct KdeGeometry {
+	x: i32,
+	y: i32,
+	width: u32,
+	height: u32,
+}
 
 #[derive(Deserialize)]
 struct KdeDesktop {
 	screen: i32,
 	id: u32,
+	geometry: Option<KdeGeometry>,
+	plugin: String,
+	image: String,
+	fill_mode: i32,
+	blur: bool,
+	color: String,
 }
 
-fn plasmashell(command: &str) -> Result<String, connection::Error> {
-	let session_path = rustbus::get_session_bus_path()?;
-	let mut con = RpcConn::connect_to_path(session_path, Timeout::Duration(Duration::from_millis(5000)))?;
-	let mut call = MessageBuilder::new()
-		.call("evaluateScript")
-		.with_interface("org.kde.PlasmaShell")
-		.on("/PlasmaShell")
-		.at("org.kde.plasmashell")
-		.build();
-	call.body.push_param(command)?;
-	let id = con.send_message(&mut call)?.write_all().map_err(force_finish_on_error)?;
-	let message = con.wait_response(id, Timeout::Duration(Duration::from_millis(5000)))?;
-	Ok(message.body.parser().get::<&str>().unwrap().to_owned())
+/// parse a color, witch kde stores as `r,g,b`
+fn parse_color(value: &str) -> Option<Color> {
+	let channels: Vec<u8> = value.split(',').filter_map(|channel| channel.trim().parse().ok()).collect();
+	match channels[..] {
+		[red, green, blue] | [red, green, blue, _] => Some(Color::new(red, green, blue)),
+		_ => None,
+	}
 }
 
-pub(crate) fn get_screens() -> Result<Vec<Screen>, WallpaperError> {
-	let desktops: Vec<KdeDesktop> = serde_json::from_str(&plasmashell("print(JSON.stringify(desktops()));")?)?;
+/// encode the value as javascript string literal
+fn js_string(value: &str) -> String {
+	let mut literal = String::with_capacity(value.len() + 2);
+	literal.push('"');
+	for char in value.chars() {
+		match char {
+			'"' => literal.push_str("\\\""),
+			'\\' => literal.push_str("\\\\"),
+			'\n' => literal.push_str("\\n"),
+			'\r' => literal.push_str("\\r"),
+			'\t' => literal.push_str("\\t"),
+			' '..='~' => literal.push(char),
+			// control characters, line separators and all other non ascii characters are escaped as utf-16
+			_ => {
+				for unit in char.encode_utf16(&mut [0; 2]) {
+					let _ = write!(literal, "\\u{unit:04x}");
+				}
+			},
+		}
+	}
+	literal.push('"');
+	literal
+}
+
+/// convert a absolute path to a `file://` url
+fn file_url(path: &Utf8Path) -> String {
+	let mut url = "file://".to_owned();
+	for byte in path.as_str().bytes() {
+		match byte {
+			b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => url.push(byte as char),
+			_ => {
+				let _ = write!(url, "%{byte:02X}");
+			},
+		}
+	}
+	url
+}
+
+/// convert a url or path, like kde stores the wallpaper, to a path
+fn path_from_url(url: &str) -> Utf8PathBuf {
+	let Some(path) = url.strip_prefix("file://") else {
+		return url.into();
+	};
+	let mut bytes = Vec::with_capacity(path.len());
+	let mut rest = path.as_bytes();
+	while let [byte, tail @ ..] = rest {
+		let decoded = match tail {
+			[high, low, ..] if *byte == b'%' => std::str::from_utf8(&[*high, *low])
+				.ok()
+				.and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+			_ => None,
+		};
+		match decoded {
+			Some(decoded) => {
+				bytes.push(decoded);
+				rest = &tail[2..];
+			},
+			None => {
+				bytes.push(*byte);
+				rest = tail;
+			},
+		}
+	}
+	String::from_utf8(bytes)
+		.map(Utf8PathBuf::from)
+		.unwrap_or_else(|_| path.into())
+}
+
+/// call of `org.kde.PlasmaShell.evaluateScript`, witch evaluates the javascript `script` at plasma
+fn plasmashell(script: String) -> DBusCall {
+	DBusCall {
+		destination: "org.kde.plasmashell".to_owned(),
+		path: "/PlasmaShell".to_owned(),
+		interface: "org.kde.PlasmaShell".to_owned(),
+		member: "evaluateScript".to_owned(),
+		args: vec![script],
+	}
+}
+
+pub(crate) fn get_screens(session: &Session) -> Result<Vec<Screen>, WallpaperError> {
+	let script = r#"
+	print(JSON.stringify(desktops().map(desktop => {
+		let geometry = null;
+		if (desktop.screen >= 0) {
+			const rect = screenGeometry(desktop.screen);
+			geometry = { x: rect.x, y: rect.y, width: rect.width, height: rect.height };
+		}
+		const plugin = desktop.wallpaperPlugin;
+		desktop.currentConfigGroup = ["Wallpaper", plugin, "General"];
+		return {
+			screen: desktop.screen,
+			id: desktop.id,
+			geometry: geometry,
+			plugin: plugin,
+			image: String(desktop.readConfig("Image", "")),
+			fill_mode: Number(desktop.readConfig("FillMode", 2)),
+			blur: String(desktop.readConfig("Blur", false)) === "true",
+			color: String(desktop.readConfig("Color", "")),
+		};
+	})));"#;
+	parse_desktops(&runner::call_dbus(session, &plasmashell(script.to_owned()))?)
+}
+
+/// parse the desktops, witch are printed by the script of [`get_screens()`]
+fn parse_desktops(reply: &str) -> Result<Vec<Screen>, WallpaperError> {
+	let desktops: Vec<KdeDesktop> = serde_json::from_str(reply).map_err(|err| WallpaperError::InvalidOutput {
+		program: "plasmashell".to_owned(),
+		error: err.into(),
+	})?;
 	let mut screens = std::vec::Vec::new();
 	for desktop in desktops {
+		let (background, mode, fill_color) = match desktop.plugin.as_str() {
+			"org.kde.color" => (parse_color(&desktop.color).map(Background::Color), None, None),
+			"org.kde.image" => {
+				let background = Some(desktop.image)
+					.filter(|image| !image.is_empty())
+					.map(|image| Background::Image(path_from_url(&image)));
+				let mode = match desktop.fill_mode {
+					0 => Some(Mode::Stretch),
+					1 if desktop.blur => Some(Mode::BlurFill),
+					1 => Some(Mode::Fit),
+					2 => Some(Mode::Crop),
+					3 => Some(Mode::Tile),
+					6 => Some(Mode::Center),
+					_ => None,
+				};
+				(background, mode, parse_color(&desktop.color))
+			},
+			// unknown wallpaper plugin, like slideshow
+			_ => (None, None, None),
+		};
 		screens.push(Screen {
 			name: desktop.id.to_string(),
 			active: desktop.screen >= 0,
-			wallpaper: None,
-			mode: None,
+			background,
+			mode,
+			fill_color,
+			focal_point: None,
+			// plasma always uses screen 0 as primary screen
+			primary: desktop.screen == 0,
+			geometry: desktop.geometry.map(|geometry| Rectangle {
+				x: geometry.x,
+				y: geometry.y,
+				width: geometry.width,
+				height: geometry.height,
+			}),
 		});
 	}
 	Ok(screens)
 }
 
-pub(crate) fn set_screens(screens: Vec<Screen>) -> Result<(), connection::Error> {
+pub(crate) fn set_screens(session: &Session, screens: Vec<Screen>) -> Result<(), WallpaperError> {
 	let mut command = r#"
-	for (const desktop of desktops()) {
-		desktop.currentConfigGroup = ["Wallpaper", "org.kde.image", "General"];"#
+	for (const desktop of desktops()) {"#
 		.to_owned();
-	for screen in screens {
-		let mode = match screen.mode.unwrap() {
+	// the names are inserted into the script, so they must be valid desktop ids
+	let ids = screens
+		.iter()
+		.map(|screen| {
+			screen
+				.name
+				.parse::<u32>()
+				.map_err(|_| WallpaperError::UnknownScreen(screen.name.clone()))
+		})
+		.collect::<Result<Vec<_>, _>>()?;
+	for (screen, id) in screens.into_iter().zip(ids) {
+		let background = screen.require_background()?.clone();
+		if let Background::Color(color) = background {
+			command += &format!(
+				r#"
+		if (desktop.id === {}){{
+			desktop.wallpaperPlugin = "org.kde.color";
+			desktop.currentConfigGroup = ["Wallpaper", "org.kde.color", "General"];
+			desktop.writeConfig("Color", "{},{},{}");
+		}}"#,
+				id, color.red, color.green, color.blue
+			);
+			continue;
+		}
+		// kde does not support gradients, so the rendered image must fill the screen
+		let mode = match background {
+			Background::Image(_) => screen.require_mode()?,
+			_ => Mode::Stretch,
+		};
+		let fill_mode = match mode {
+			Mode::BlurFill | Mode::Fit => 1,
 			Mode::Center => 6,
 			Mode::Crop => 2,
-			Mode::Fit => 1,
-			Mode::Stretch => 0,
+			Mode::Span | Mode::Stretch => 0,
 			Mode::Tile => 3,
 		};
-		write!(
-			command,
+		let fill_color = screen
+			.fill_color
+			.map(|color| {
+				format!(
+					r#"
+			desktop.writeConfig("Color", "{},{},{}");"#,
+					color.red, color.green, color.blue
+				)
+			})
+			.unwrap_or_default();
+		command += &format!(
 			r#"
 		if (desktop.id === {}){{
+			desktop.wallpaperPlugin = "org.kde.image";
+			desktop.currentConfigGroup = ["Wallpaper", "org.kde.image", "General"];
 			desktop.writeConfig("FillMode", {});
-			desktop.writeConfig("Image", {:?});
+			desktop.writeConfig("Blur", {});
+			desktop.writeConfig("Image", {});{}
 		}}"#,
-			screen.name,
-			mode,
-			screen.wallpaper.as_ref().unwrap().as_str()
-		)
-		.unwrap();
+			id,
+			fill_mode,
+			// kde does natively support a blurred background for PreserveAspectFit
+			mode == Mode::BlurFill,
+			js_string(&file_url(&render::background_image(session, &background)?)),
+			fill_color
+		);
 	}
 	command += r#"
 	}"#;
-	plasmashell(&command)?;
+	runner::apply_dbus(session, plasmashell(command))?;
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::linux::runner::tests::{screen, scripted};
+
+	#[test]
+	fn parse_desktops() {
+		let screens = super::parse_desktops(
+			r#"[{"screen": 0, "id": 1, "geometry": {"x": 0, "y": 0, "width": 1920, "height": 1080},
+			"plugin": "org.kde.image", "image": "file:///wallpaper%20one.jpg", "fill_mode": 1, "blur": true, "color": "0,0,0"},
+			{"screen": -1, "id": 2, "geometry": null, "plugin": "org.kde.color", "image": "", "fill_mode": 2, "blur": false, "color": "255,0,0"}]"#,
+		)
+		.unwrap();
+		assert_eq!(screens.len(), 2);
+		assert_eq!(screens[0].name, "1");
+		assert!(screens[0].primary);
+		assert_eq!(screens[0].background, Some(Background::Image("/wallpaper one.jpg".into())));
+		assert_eq!(screens[0].mode, Some(Mode::BlurFill));
+		assert!(!screens[1].active);
+		assert_eq!(screens[1].background, Some(Background::Color(Color::new(255, 0, 0))));
+	}
+
+	#[test]
+	fn malformed_reply() {
+		let replies = [
+			"",
+			"not json",
+			"[{}]",
+			r#"[{"screen": 0, "id": 1}]"#,
+			r#"[{"screen": 0, "id": -1, "geometry": null, "plugin": "", "image": "", "fill_mode": 0, "blur": false, "color": ""}]"#,
+		];
+		for reply in replies {
+			let result = super::parse_desktops(reply);
+			assert!(
+				matches!(result, Err(WallpaperError::InvalidOutput { .. })),
+				"{reply:?}: {result:?}"
+			);
+		}
+		let result = get_screens(&scripted(b"not json"));
+		assert!(matches!(result, Err(WallpaperError::InvalidOutput { .. })));
+	}
+
+	#[test]
+	fn js_string() {
+		let cases = [
+			("wallpaper", r#""wallpaper""#),
+			("say \"hi\"", r#""say \"hi\"""#),
+			("C:\\wallpaper", r#""C:\\wallpaper""#),
+			("line\nbreak", r#""line\nbreak""#),
+			("line\u{2028}separator", r#""line\u2028separator""#),
+			("emoji \u{1f600}", r#""emoji \ud83d\ude00""#),
+			("gr\u{fc}n", r#""gr\u00fcn""#),
+		];
+		for (value, literal) in cases {
+			assert_eq!(super::js_string(value), literal, "{value:?}");
+		}
+	}
+
+	#[test]
+	fn file_url() {
+		let paths = [
+			"/home/user/my wallpaper.jpg",
+			"/home/user/100%.jpg",
+			"/home/user/%20.jpg",
+			"/home/user/#1.jpg",
+			"/home/user/gr\u{fc}n/\u{65e5}\u{672c}.jpg",
+		];
+		for path in paths {
+			let url = super::file_url(Utf8Path::new(path));
+			assert!(url.starts_with("file:///"), "{url}");
+			assert!(!url[7..].contains([' ', '#']), "{url}");
+			assert_eq!(path_from_url(&url), path);
+		}
+		assert_eq!(
+			super::file_url(Utf8Path::new("/my wallpaper.jpg")),
+			"file:///my%20wallpaper.jpg"
+		);
+	}
+
+	#[test]
+	fn unknown_screen() {
+		// the name is checked before the incomplete first screen is added to the script
+		let screens = vec![screen("1"), screen("1){print(\"injected\")}//")];
+		let result = set_screens(&scripted(b""), screens);
+		assert!(matches!(result, Err(WallpaperError::UnknownScreen(name)) if name.starts_with("1)")));
+	}
+}
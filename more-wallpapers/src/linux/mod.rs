@@ -1,94 +1,337 @@
-use crate::{error::CommandError, load_env_var, Environment, WallpaperBuilder, WallpaperError};
-use std::{ffi::OsStr, process::Command};
+use crate::{
+	backend::{self, BuiltinBackend, GetScreens, Session, SetScreens},
+	error::Context,
+	load_env_var, plan, Capabilities, Environment, MissingRequirement, Screen, WallpaperBuilder, WallpaperError,
+};
+use camino::Utf8PathBuf;
+pub use detect::DetectionReport;
+pub(crate) use runner::DEFAULT_TIMEOUT;
+use runner::{apply_command, run_command};
+pub use runner::{CommandRunner, DefaultRunner, FlatpakRunner, RunContext};
+use std::{ffi::OsStr, fs, process::Command};
 
 mod cinnamon;
+mod detect;
 mod kde;
+pub(crate) mod render;
+mod requirements;
+mod runner;
+mod state;
 mod sway;
 mod x11;
 mod xfce;
 
-#[cfg(feature = "fallback")]
-mod wallpaper_crate;
+pub(crate) fn get_environment() -> Result<Environment, WallpaperError> {
+	let report = DetectionReport::detect();
+	report.environment.ok_or(WallpaperError::Unsuported(report.reason))
+}
 
-fn get_environment() -> Result<Environment, WallpaperError> {
-	#[cfg(feature = "fallback")]
-	{
-		//if the SWAYSOCK env exist sawy is the active desktop
-		let sway_sock = load_env_var("SWAYSOCK");
-		if sway_sock.is_ok() {
-			return Ok(Environment::Sway);
-		}
-	}
-	let desktop = load_env_var("XDG_CURRENT_DESKTOP")?.to_lowercase();
-	if desktop.as_str() == "x-cinnamon" {
-		return Ok(Environment::Cinnamon);
-	}
-	if desktop.as_str() == "kde" {
-		return Ok(Environment::Kde);
-	}
-	if desktop.as_str() == "xfce" {
-		return Ok(Environment::Xfce);
-	}
-	let sessinon_type = load_env_var("XDG_SESSION_TYPE")?.to_lowercase();
-	match sessinon_type.as_str() {
-		"x11" => Ok(Environment::X11),
-		#[cfg(feature = "fallback")]
-		"wayland" => match desktop.as_str() {
-			"budgie:gnome" | "deepin" | "gnome" | "lxde" | "mate" => Ok(Environment::LinuxFallback),
-			_ => Err(WallpaperError::Unsuported(format!("{desktop} ({sessinon_type})"))),
-		},
-		_ => Err(WallpaperError::Unsuported(format!("{desktop} ({sessinon_type})"))),
-	}
+/// return the built-in environment, witch can be used at the current session
+pub(crate) fn detect_builtin() -> Option<Environment> {
+	DetectionReport::detect_builtin().environment
 }
 
-pub(crate) fn get_builder() -> Result<WallpaperBuilder, WallpaperError> {
-	let environment = get_environment()?;
-	let screens = match environment {
-		Environment::Cinnamon => cinnamon::get_screens()?,
-		Environment::Kde => kde::get_screens()?,
-		Environment::Sway => sway::get_screens()?,
-		Environment::X11 => x11::get_screens()?,
-		Environment::Xfce => xfce::get_screens()?,
+pub(crate) fn builtin_backend(environment: Environment) -> Option<BuiltinBackend> {
+	let (get_screens, set_screens): (GetScreens, SetScreens) = match environment {
+		Environment::Cinnamon => (cinnamon::get_screens, |session, screens, _changed| {
+			// unchanged screens are required, because cinnamon always sets all screens
+			cinnamon::set_screens(session, screens)
+		}),
+		Environment::Kde => (kde::get_screens, |session, screens, changed| {
+			kde::set_screens(session, only_changed(&screens, changed))
+		}),
+		Environment::Sway => (sway::get_screens, |session, screens, changed| {
+			set_screens_transactional(session, Environment::Sway, only_changed(&screens, changed), sway::set_screen)
+		}),
+		Environment::X11 => (
+			|_session| Ok(x11::get_screens()?),
+			|session, screens, _changed| {
+				// xwallpaper always sets all screens, so the unchanged screens are set to their current wallpaper
+				x11::set_screens(session, screens)
+			},
+		),
+		Environment::Xfce => (xfce::get_screens, |session, screens, changed| {
+			set_screens_transactional(session, Environment::Xfce, only_changed(&screens, changed), xfce::set_screen)
+		}),
 		#[cfg(feature = "fallback")]
-		Environment::LinuxFallback => wallpaper_crate::get_screens(),
+		Environment::LinuxFallback => (crate::wallpaper_crate::get_screens, crate::wallpaper_crate::set_screens),
+		Environment::Custom(_) => return None,
 	};
-	Ok(WallpaperBuilder { environment, screens })
+	Some(BuiltinBackend {
+		environment,
+		get_screens,
+		set_screens,
+	})
+}
+
+pub(crate) fn get_builder(environment: Environment, session: Session) -> Result<WallpaperBuilder, WallpaperError> {
+	let screens = get_screens(&session, environment)?;
+	Ok(WallpaperBuilder::from_screens(environment, screens, session))
+}
+
+#[cfg_attr(
+	feature = "tracing",
+	tracing::instrument(level = "debug", skip_all, fields(environment = %environment))
+)]
+fn get_screens(session: &Session, environment: Environment) -> Result<Vec<Screen>, WallpaperError> {
+	requirements::check(session, environment)?;
+	let mut screens = backend::backend(environment)?.get_screens(session)?;
+	// not all environments can report the current wallpaper
+	state::restore(environment, &mut screens);
+	Ok(screens)
 }
 
+pub(crate) fn missing_requirements(environment: Environment) -> Vec<MissingRequirement> {
+	requirements::missing(&Session::default(), environment)
+}
+
+#[cfg_attr(
+	feature = "tracing",
+	tracing::instrument(level = "debug", name = "set_screens", skip_all, fields(environment = %builder.environment))
+)]
 pub(crate) fn set_screens_from_builder(builder: WallpaperBuilder) -> Result<(), WallpaperError> {
-	match builder.environment {
-		Environment::Cinnamon => cinnamon::set_screens(builder.screens)?,
-		Environment::Kde => kde::set_screens(builder.screens)?,
-		Environment::Sway => sway::set_screens(builder.screens)?,
-		Environment::X11 => x11::set_screens(builder.screens)?,
-		Environment::Xfce => xfce::set_screens(builder.screens)?,
-		#[cfg(feature = "fallback")]
-		Environment::LinuxFallback => wallpaper_crate::set_screens(builder.screens)?,
+	let session = &builder.session;
+	let backend = backend::backend(builder.environment)?;
+	requirements::check_set(session, builder.environment)?;
+	// unchanged screens keep their current state, screens with unknown state can not be passed to the backends
+	let (screens, changed): (Vec<Screen>, Vec<bool>) = builder
+		.screens
+		.into_iter()
+		.zip(builder.changed)
+		.filter(|(screen, _)| screen.is_set())
+		.unzip();
+	let recorded_screens = only_changed(&screens, &changed);
+	let screens = render::prepare(session, screens, &changed, &backend.capabilities())?;
+	let used_screens = screens.clone();
+	backend::set_screens(backend.as_ref(), session, screens, &changed)?;
+	if !plan::is_planning() {
+		render::clean_cache(&used_screens);
+		// the wallpapers are already set, so a failed record must not fail the whole operation
+		#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+		if let Err(err) = state::save(builder.environment, &recorded_screens) {
+			#[cfg(feature = "tracing")]
+			tracing::warn!(error = %err, "failed to record the wallpapers");
+		}
 	}
 	Ok(())
 }
 
+/// return only the changed screens
+fn only_changed(screens: &[Screen], changed: &[bool]) -> Vec<Screen> {
+	screens
+		.iter()
+		.zip(changed)
+		.filter(|(_, changed)| **changed)
+		.map(|(screen, _)| screen.clone())
+		.collect()
+}
+
+/// Apply the screens one by one with `set_screen`.
+/// If one screen fails, all screens changed so far are reset to the state before.
+/// Screens with unknown previous state can not be reset.
+fn set_screens_transactional<F>(
+	session: &Session,
+	environment: Environment,
+	screens: Vec<Screen>,
+	set_screen: F,
+) -> Result<(), WallpaperError>
+where
+	F: FnMut(&Session, Screen) -> Result<(), WallpaperError>,
+{
+	let previous = get_screens(session, environment)?;
+	set_screens_with_rollback(session, previous, screens, &environment.capabilities(), set_screen)
+}
+
+/// Like [`set_screens_transactional()`], but the `previous` state of the screens is passed.
+fn set_screens_with_rollback<F>(
+	session: &Session,
+	previous: Vec<Screen>,
+	screens: Vec<Screen>,
+	capabilities: &Capabilities,
+	mut set_screen: F,
+) -> Result<(), WallpaperError>
+where
+	F: FnMut(&Session, Screen) -> Result<(), WallpaperError>,
+{
+	let mut changed = Vec::new();
+	for screen in screens {
+		// the failing screen may be changed partially, so it is reset too
+		changed.push(screen.name.clone());
+		if let Err(error) = set_screen(session, screen) {
+			let mut rollback_errors = Vec::new();
+			let previous: Vec<Screen> = previous
+				.into_iter()
+				.filter(|screen| changed.contains(&screen.name) && screen.is_set())
+				.collect();
+			let reset = vec![true; previous.len()];
+			match render::prepare(session, previous, &reset, capabilities) {
+				Ok(previous) => {
+					for screen in previous {
+						if let Err(error) = set_screen(session, screen) {
+							rollback_errors.push(error);
+						}
+					}
+				},
+				Err(error) => rollback_errors.push(error),
+			}
+			return Err(WallpaperError::Rollback {
+				error: Box::new(error),
+				rollback_errors,
+			});
+		}
+	}
+	Ok(())
+}
+
+/// Return the `more-wallpapers` directory inside the xdg base directory of `var`.
+/// If `var` is not set, `default` relative to the home directory is used instead.
+/// The directory is not created, see [`create_xdg_dir()`].
+fn xdg_dir(var: &'static str, default: &str) -> Result<Utf8PathBuf, WallpaperError> {
+	let dir = match load_env_var(var) {
+		Ok(dir) if !dir.is_empty() => Utf8PathBuf::from(dir),
+		_ => Utf8PathBuf::from(load_env_var("HOME")?).join(default),
+	};
+	Ok(dir.join("more-wallpapers"))
+}
+
+/// Like [`xdg_dir()`], but the directory is created, if it does not exist.
+fn create_xdg_dir(var: &'static str, default: &str) -> Result<Utf8PathBuf, WallpaperError> {
+	let dir = xdg_dir(var, default)?;
+	fs::create_dir_all(&dir).context(&dir)?;
+	Ok(dir)
+}
+
+/// convert the output of a program to a string
+fn utf8_output(program: &str, output: Vec<u8>) -> Result<String, WallpaperError> {
+	String::from_utf8(output).map_err(|err| WallpaperError::InvalidOutput {
+		program: program.to_owned(),
+		error: err.into(),
+	})
+}
+
 /// run a command, check error code and convert the result
-fn run<I, S>(program: &str, args: I) -> Result<Vec<u8>, CommandError>
+fn run<I, S>(session: &Session, program: &str, args: I) -> Result<Vec<u8>, WallpaperError>
 where
 	I: IntoIterator<Item = S>,
 	S: AsRef<OsStr>,
 {
 	let mut command = Command::new(program);
 	command.args(args);
-	run_command(command)
+	run_command(session, command)
 }
 
-/// allow also checking more complex commands
-fn run_command(mut command: Command) -> Result<Vec<u8>, CommandError> {
-	let output = command.output();
-	let output = output.map_err(|err| CommandError::CommandIO(command.get_program().into(), err))?;
-	if !output.status.success() {
-		return Err(CommandError::CommandStatus {
-			command,
-			exit_code: output.status.code(),
-			stderr: output.stderr,
-		});
+#[cfg(test)]
+mod tests {
+	use super::{
+		runner::tests::{screen, scripted},
+		*,
+	};
+	use crate::{Background, Mode};
+
+	#[test]
+	fn incomplete_screen() {
+		type SetScreen = fn(&Session, Screen) -> Result<(), WallpaperError>;
+		let backends: &[(&str, SetScreen)] = &[
+			// the default screen of cinnamon is inactive, but must have a background too
+			("default", |session, mut screen| {
+				screen.active = false;
+				cinnamon::set_screens(session, vec![screen])
+			}),
+			("1", |session, screen| kde::set_screens(session, vec![screen])),
+			("DP-1", sway::set_screen),
+			("DP-1", |session, screen| x11::set_screens(session, vec![screen])),
+			("screen0/monitor0/workspace0", xfce::set_screen),
+			#[cfg(feature = "fallback")]
+			("Unknow", |session, screen| {
+				crate::wallpaper_crate::set_screens(session, vec![screen], &[true])
+			}),
+		];
+		let session = scripted(b"");
+		for (name, set_screen) in backends {
+			let result = set_screen(&session, screen(name));
+			assert!(
+				matches!(
+					result,
+					Err(WallpaperError::IncompleteScreen {
+						missing: "background",
+						..
+					})
+				),
+				"{name}: {result:?}"
+			);
+			let mut screen = screen(name);
+			screen.background = Some(Background::Image("/wallpaper.jpg".into()));
+			let result = set_screen(&session, screen);
+			assert!(
+				matches!(result, Err(WallpaperError::IncompleteScreen { missing: "mode", .. })),
+				"{name}: {result:?}"
+			);
+		}
+	}
+
+	fn with_wallpaper(name: &str, wallpaper: &str) -> Screen {
+		let mut screen = screen(name);
+		screen.background = Some(Background::Image(wallpaper.into()));
+		screen.mode = Some(Mode::Crop);
+		screen
+	}
+
+	#[test]
+	fn failed_screen_is_rolled_back() {
+		let previous = vec![
+			with_wallpaper("DP-1", "/old-1.jpg"),
+			with_wallpaper("DP-2", "/old-2.jpg"),
+			// screens with unknown state can not be reset
+			screen("DP-3"),
+			with_wallpaper("HDMI-1", "/old-hdmi.jpg"),
+		];
+		let screens = vec![
+			with_wallpaper("DP-1", "/new-1.jpg"),
+			with_wallpaper("DP-3", "/new-3.jpg"),
+			with_wallpaper("DP-2", "/new-2.jpg"),
+			with_wallpaper("HDMI-1", "/new-hdmi.jpg"),
+		];
+		let mut applied = Vec::new();
+		let result = set_screens_with_rollback(
+			&scripted(b""),
+			previous,
+			screens,
+			&Capabilities::default(),
+			|_session, screen| {
+				let wallpaper = screen.wallpaper().unwrap().to_string();
+				applied.push(wallpaper.clone());
+				if wallpaper == "/new-2.jpg" {
+					return Err(WallpaperError::Cancelled);
+				}
+				Ok(())
+			},
+		);
+		assert!(matches!(result, Err(WallpaperError::Rollback { rollback_errors, .. }) if rollback_errors.is_empty()));
+		// the screens after the failed one are not changed, the failed one is reset too
+		assert_eq!(applied, [
+			"/new-1.jpg",
+			"/new-3.jpg",
+			"/new-2.jpg",
+			"/old-1.jpg",
+			"/old-2.jpg"
+		]);
+	}
+
+	#[test]
+	fn transactional_screens_are_set_in_order() {
+		let screens = vec![with_wallpaper("DP-1", "/1.jpg"), with_wallpaper("DP-2", "/2.jpg")];
+		let mut applied = Vec::new();
+		set_screens_with_rollback(
+			&scripted(b""),
+			Vec::new(),
+			screens,
+			&Capabilities::default(),
+			|_session, screen| {
+				applied.push(screen.name);
+				Ok(())
+			},
+		)
+		.unwrap();
+		assert_eq!(applied, ["DP-1", "DP-2"]);
 	}
-	Ok(output.stdout)
 }
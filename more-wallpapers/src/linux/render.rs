@@ -0,0 +1,314 @@
+//! Emulate features, which are not natively supported by the environment, by pre-rendering images with [ImageMagick].
+//!
+//! [ImageMagick]: https://imagemagick.org
+
+use super::{create_xdg_dir, run_command, xdg_dir};
+use crate::{
+	backend::Session, error::Context, Background, Capabilities, FocalPoint, GradientDirection, Mode, Rectangle, Screen,
+	WallpaperError,
+};
+use camino::{Utf8Path, Utf8PathBuf};
+use std::{
+	collections::hash_map::DefaultHasher,
+	fs,
+	hash::{Hash, Hasher},
+	process::{self, Command},
+	time::Duration,
+};
+
+/// rendered images, witch have not been used for this time, are removed by [`clean_cache()`]
+const CACHE_MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Apply the `convert` operations on the input image and return the path of the rendered image.
+/// If no input image is given, the operations must generate the image.
+/// The image is only rendered again, if the input image or the operations have changed.
+fn render(session: &Session, input: Option<&Utf8Path>, operations: &[String]) -> Result<Utf8PathBuf, WallpaperError> {
+	let mut hasher = DefaultHasher::new();
+	if let Some(input) = input {
+		let modified = input.metadata().and_then(|metadata| metadata.modified()).context(input)?;
+		input.hash(&mut hasher);
+		modified.hash(&mut hasher);
+	}
+	operations.hash(&mut hasher);
+	let hash = hasher.finish();
+	let dir = create_xdg_dir("XDG_CACHE_HOME", ".cache")?;
+	let output = dir.join(format!("{hash:016x}.png"));
+	if !output.exists() {
+		// render to a temporary file, so a interrupted convert does not leave a truncated image in the cache
+		let temp = dir.join(format!("{hash:016x}.{}.tmp.png", process::id()));
+		let mut command = Command::new("convert");
+		command.args(input).args(operations).arg(&temp);
+		let result = run_command(session, command).and_then(|_| fs::rename(&temp, &output).context(&output));
+		if result.is_err() {
+			let _ = fs::remove_file(&temp);
+		}
+		result?;
+	}
+	Ok(output)
+}
+
+/// Remove rendered images, witch are older than [`CACHE_MAX_AGE`] and are not used by any of the `screens`.
+/// The cache is only a optimization, so errors are ignored.
+pub(super) fn clean_cache(screens: &[Screen]) {
+	if let Ok(dir) = xdg_dir("XDG_CACHE_HOME", ".cache") {
+		remove_expired(&dir, screens, CACHE_MAX_AGE);
+	}
+}
+
+/// remove the images of `dir`, witch are older than `max_age` and are not used by any of the `screens`
+fn remove_expired(dir: &Utf8Path, screens: &[Screen], max_age: Duration) {
+	let Ok(entries) = dir.read_dir_utf8() else {
+		return;
+	};
+	for entry in entries.filter_map(Result::ok) {
+		let path = entry.path();
+		let expired = entry
+			.metadata()
+			.and_then(|metadata| metadata.modified())
+			.ok()
+			.and_then(|modified| modified.elapsed().ok())
+			.map_or(false, |age| age >= max_age);
+		let used = screens.iter().any(|screen| screen.wallpaper() == Some(path));
+		if expired && !used && path.extension() == Some("png") {
+			let _ = fs::remove_file(path);
+		}
+	}
+}
+
+/// Return a image of the background.
+/// Colors and gradients are rendered into an image, witch must be stretched to the screen size.
+pub(crate) fn background_image(session: &Session, background: &Background) -> Result<Utf8PathBuf, WallpaperError> {
+	match background {
+		Background::Image(path) => Ok(path.to_owned()),
+		Background::Color(color) => render(session, None, &[
+			"-size".to_owned(),
+			"16x16".to_owned(),
+			format!("xc:{color}"),
+		]),
+		Background::Gradient { direction, start, end } => {
+			let mut operations = vec!["-size".to_owned(), "1024x1024".to_owned(), format!("gradient:{start}-{end}")];
+			// convert does create vertical gradients
+			if *direction == GradientDirection::Horizontal {
+				operations.extend(["-rotate".to_owned(), "-90".to_owned()]);
+			}
+			render(session, None, &operations)
+		},
+	}
+}
+
+/// Fill the empty space left by [`Mode::Fit`] and [`Mode::Center`] with the fill color of the screen.
+/// Return `None`, if no pre-rendered image is needed or the size of the screen is unknown.
+pub(super) fn fill(session: &Session, wallpaper: &Utf8Path, screen: &Screen) -> Result<Option<Utf8PathBuf>, WallpaperError> {
+	let (Some(color), Some(geometry)) = (screen.fill_color, screen.geometry) else {
+		return Ok(None);
+	};
+	let size = format!("{}x{}", geometry.width, geometry.height);
+	let mut operations = match screen.mode {
+		Some(Mode::Fit) => vec!["-resize".to_owned(), size.clone()],
+		Some(Mode::Center) => Vec::new(),
+		_ => return Ok(None),
+	};
+	operations.extend([
+		"-background".to_owned(),
+		color.to_string(),
+		"-gravity".to_owned(),
+		"center".to_owned(),
+		"-extent".to_owned(),
+		size,
+	]);
+	render(session, Some(wallpaper), &operations).map(Some)
+}
+
+/// Crop the wallpaper to the size of the screen, so that the focal point is as centered as possible.
+fn focal_crop(
+	session: &Session,
+	wallpaper: &Utf8Path,
+	focal_point: FocalPoint,
+	geometry: Rectangle,
+) -> Result<Utf8PathBuf, WallpaperError> {
+	let mut command = Command::new("identify");
+	command.args(["-format", "%w %h"]).arg(format!("{wallpaper}[0]"));
+	let output = String::from_utf8_lossy(&run_command(session, command)?).into_owned();
+	let size: Vec<f64> = output.split_whitespace().filter_map(|value| value.parse().ok()).collect();
+	let [width, height] = size[..] else {
+		return Err(WallpaperError::UnknownImageSize(wallpaper.to_owned()));
+	};
+	let (screen_width, screen_height) = (geometry.width as f64, geometry.height as f64);
+	let scale = (screen_width / width).max(screen_height / height);
+	let (width, height) = ((width * scale).ceil(), (height * scale).ceil());
+	let x = (focal_point.x as f64 * width - screen_width / 2.0).clamp(0.0, width - screen_width);
+	let y = (focal_point.y as f64 * height - screen_height / 2.0).clamp(0.0, height - screen_height);
+	render(session, Some(wallpaper), &[
+		"-resize".to_owned(),
+		format!("{width}x{height}!"),
+		"-crop".to_owned(),
+		format!("{}x{}+{}+{}", geometry.width, geometry.height, x.round(), y.round()),
+		"+repage".to_owned(),
+	])
+}
+
+/// union of the rectangles of all active screens
+fn canvas(screens: &[Screen]) -> Option<Rectangle> {
+	screens
+		.iter()
+		.filter(|screen| screen.active)
+		.filter_map(|screen| screen.geometry)
+		.reduce(|a, b| {
+			let x = a.x.min(b.x);
+			let y = a.y.min(b.y);
+			let right = (a.x + a.width as i32).max(b.x + b.width as i32);
+			let bottom = (a.y + a.height as i32).max(b.y + b.height as i32);
+			Rectangle {
+				x,
+				y,
+				width: (right - x) as u32,
+				height: (bottom - y) as u32,
+			}
+		})
+}
+
+/// Return the part of `wallpaper`, which is visible at `screen`,
+/// if the wallpaper is spanned over all active `screens`.
+pub(super) fn span(
+	session: &Session,
+	wallpaper: &Utf8Path,
+	screen: &Screen,
+	screens: &[Screen],
+) -> Result<Utf8PathBuf, WallpaperError> {
+	let (Some(geometry), Some(canvas)) = (screen.geometry, canvas(screens)) else {
+		return Err(WallpaperError::UnknownGeometry(screen.name.clone()));
+	};
+	render(session, Some(wallpaper), &[
+		"-resize".to_owned(),
+		format!("{}x{}^", canvas.width, canvas.height),
+		"-gravity".to_owned(),
+		"center".to_owned(),
+		"-extent".to_owned(),
+		format!("{}x{}", canvas.width, canvas.height),
+		"+gravity".to_owned(),
+		"-crop".to_owned(),
+		format!(
+			"{}x{}+{}+{}",
+			geometry.width,
+			geometry.height,
+			geometry.x - canvas.x,
+			geometry.y - canvas.y
+		),
+		"+repage".to_owned(),
+	])
+}
+
+/// Fit the wallpaper into the screen and fill the empty space with a blurred copy of the wallpaper.
+fn blur_fill(session: &Session, wallpaper: &Utf8Path, geometry: Rectangle) -> Result<Utf8PathBuf, WallpaperError> {
+	let size = format!("{}x{}", geometry.width, geometry.height);
+	// The first clone is the blurred background. It is downscaled before blurring, to keep blurring fast for large images.
+	// The second clone is the fitted foreground.
+	let operations = format!(
+		"( -clone 0 -resize {size}^ -gravity center -extent {size} -scale 10% -blur 0x4 -resize {size}! ) \
+		 ( -clone 0 -resize {size} ) -delete 0 -gravity center -composite"
+	);
+	let operations: Vec<String> = operations.split_whitespace().map(str::to_owned).collect();
+	render(session, Some(wallpaper), &operations)
+}
+
+/// Replace modes, which are not supported by the environment, with pre-rendered images.
+/// If the size of the screen is unknown, a similar mode is used instead.
+/// Cropped wallpapers with a focal point are also pre-rendered.
+///
+/// So the backends only receive [`Mode::BlurFill`] and [`Mode::Span`], if the environment supports them
+/// according to its [`Capabilities`]. Otherwise they get a stretched image, [`Mode::Fit`] or [`Mode::Crop`].
+///
+/// Only the `changed` screens are prepared, the unchanged screens are returned as they are.
+pub(super) fn prepare(
+	session: &Session,
+	mut screens: Vec<Screen>,
+	changed: &[bool],
+	capabilities: &Capabilities,
+) -> Result<Vec<Screen>, WallpaperError> {
+	// the unchanged screens are still part of the canvas of a spanned wallpaper
+	let all_screens = screens.clone();
+	for (screen, _) in screens.iter_mut().zip(changed).filter(|(_, changed)| **changed) {
+		let (Some(background), Some(mode)) = (&screen.background, screen.mode) else {
+			continue;
+		};
+		if mode == Mode::Crop {
+			let (Some(wallpaper), Some(geometry)) = (background.image(), screen.geometry) else {
+				continue;
+			};
+			let focal_point = match screen.focal_point {
+				Some(focal_point) => focal_point,
+				None if session.focal_point_metadata => match FocalPoint::from_metadata(wallpaper) {
+					Some(focal_point) => focal_point,
+					None => continue,
+				},
+				None => continue,
+			};
+			screen.background = Some(Background::Image(focal_crop(session, wallpaper, focal_point, geometry)?));
+			screen.mode = Some(Mode::Stretch);
+			continue;
+		}
+		if capabilities.modes.contains(&mode) {
+			continue;
+		}
+		let rendered = match (mode, screen.geometry) {
+			(Mode::Span, Some(_)) => span(session, &background_image(session, background)?, screen, &all_screens)?,
+			(Mode::BlurFill, Some(geometry)) => blur_fill(session, &background_image(session, background)?, geometry)?,
+			(Mode::Span, None) => {
+				// inactive screens are not part of the canvas
+				screen.mode = Some(Mode::Crop);
+				continue;
+			},
+			(Mode::BlurFill, None) => {
+				screen.mode = Some(Mode::Fit);
+				continue;
+			},
+			_ => continue,
+		};
+		screen.background = Some(Background::Image(rendered));
+		screen.mode = Some(Mode::Stretch);
+	}
+	Ok(screens)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::linux::runner::tests::{screen, scripted};
+
+	#[test]
+	fn unchanged_screens_are_not_prepared() {
+		let mut unchanged = screen("DP-1");
+		// a missing wallpaper would fail rendering
+		unchanged.background = Some(Background::Image("/does/not/exist.jpg".into()));
+		unchanged.mode = Some(Mode::BlurFill);
+		unchanged.geometry = Some(Rectangle {
+			x: 0,
+			y: 0,
+			width: 1920,
+			height: 1080,
+		});
+		let screens = prepare(&scripted(b""), vec![unchanged.clone()], &[false], &Capabilities::default()).unwrap();
+		assert_eq!(screens[0].background, unchanged.background);
+		assert_eq!(screens[0].mode, Some(Mode::BlurFill));
+	}
+
+	#[test]
+	fn expired_images_are_removed() {
+		let dir = Utf8PathBuf::from_path_buf(std::env::temp_dir())
+			.unwrap()
+			.join(format!("more-wallpapers-test-{}", process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		let used = dir.join("used.png");
+		let unused = dir.join("unused.png");
+		let other = dir.join("other.txt");
+		for path in [&used, &unused, &other] {
+			fs::write(path, b"").unwrap();
+		}
+		let mut screen = screen("DP-1");
+		screen.background = Some(Background::Image(used.clone()));
+		remove_expired(&dir, &[screen], Duration::ZERO);
+		let exists = [used.exists(), unused.exists(), other.exists()];
+		fs::remove_dir_all(&dir).unwrap();
+		assert_eq!(exists, [true, false, true]);
+	}
+}
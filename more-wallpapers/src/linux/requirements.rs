@@ -0,0 +1,200 @@
+use super::runner;
+use crate::{backend::Session, Environment, MissingRequirement, WallpaperError};
+
+const IMAGEMAGICK_HINT: &str = "install ImageMagick, witch is required for color and gradient backgrounds";
+
+/// runtime dependency of a environment
+enum Requirement {
+	/// external program, witch is looked up by the runner
+	Program { program: &'static str, hint: &'static str },
+	/// x11 display with the RandR extension, used by `x11::get_screens()`
+	Xrandr,
+}
+
+impl Requirement {
+	fn check(&self, session: &Session, environment: Environment) -> Result<(), MissingRequirement> {
+		let (tool, hint, available) = match self {
+			Self::Program { program, hint } => (*program, *hint, runner::is_installed(session, program)),
+			Self::Xrandr => (
+				"X server with RandR",
+				"a running X server with the RandR extension is required, check if DISPLAY is set",
+				xrandr::XHandle::open().and_then(|mut handle| handle.monitors()).is_ok(),
+			),
+		};
+		if available {
+			Ok(())
+		} else {
+			Err(MissingRequirement {
+				tool: tool.to_owned(),
+				environment,
+				hint: hint.to_owned(),
+			})
+		}
+	}
+
+	/// whether the requirement is only used to read the screens.
+	/// Connecting to the X server is expensive, so it is not repeated while setting the wallpapers.
+	fn read_only(&self) -> bool {
+		matches!(self, Self::Xrandr)
+	}
+}
+
+const XWALLPAPER: Requirement = Requirement::Program {
+	program: "xwallpaper",
+	hint: "install xwallpaper",
+};
+
+/// return the dependencies, witch are required to read the screens
+fn read_requirements(environment: Environment) -> Vec<Requirement> {
+	use Requirement::*;
+	match environment {
+		Environment::Cinnamon => vec![
+			Program {
+				program: "dconf",
+				hint: "install dconf (`dconf-cli` at debian based distributions)",
+			},
+			Xrandr,
+		],
+		Environment::Sway => vec![Program {
+			program: "swaymsg",
+			hint: "swaymsg is part of sway, check if sway is installed and at PATH",
+		}],
+		Environment::X11 => vec![Xrandr],
+		Environment::Xfce => vec![Program {
+			program: "xfconf-query",
+			hint: "install xfconf",
+		}],
+		_ => Vec::new(),
+	}
+}
+
+/// return the additional dependencies, witch are only required to set the wallpapers
+fn set_requirements(environment: Environment) -> Vec<Requirement> {
+	match environment {
+		// cinnamon sets the active screens by xwallpaper
+		Environment::Cinnamon | Environment::X11 => vec![XWALLPAPER],
+		_ => Vec::new(),
+	}
+}
+
+/// Check the required dependencies of the environment, before the screens are read.
+/// Return the first missing dependency.
+pub(super) fn check(session: &Session, environment: Environment) -> Result<(), WallpaperError> {
+	read_requirements(environment)
+		.iter()
+		.try_for_each(|requirement| requirement.check(session, environment))?;
+	Ok(())
+}
+
+/// Check the required dependencies of the environment, before the wallpapers are set.
+/// The dependencies, witch are only required to read the screens, are skipped.
+pub(super) fn check_set(session: &Session, environment: Environment) -> Result<(), WallpaperError> {
+	read_requirements(environment)
+		.iter()
+		.filter(|requirement| !requirement.read_only())
+		.chain(&set_requirements(environment))
+		.try_for_each(|requirement| requirement.check(session, environment))?;
+	Ok(())
+}
+
+/// return all missing dependencies of the environment, including optional ones
+pub(crate) fn missing(session: &Session, environment: Environment) -> Vec<MissingRequirement> {
+	let optional = [
+		Requirement::Program {
+			program: "convert",
+			hint: IMAGEMAGICK_HINT,
+		},
+		Requirement::Program {
+			program: "identify",
+			hint: IMAGEMAGICK_HINT,
+		},
+	];
+	read_requirements(environment)
+		.iter()
+		.chain(&set_requirements(environment))
+		.chain(&optional)
+		.filter_map(|requirement| requirement.check(session, environment).err())
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{CommandRunner, RunContext};
+	use std::{process::Command, sync::Arc};
+
+	/// runner, witch has only the given programs installed
+	struct Installed(&'static [&'static str]);
+
+	impl CommandRunner for Installed {
+		fn run(&self, _command: Command, _context: &RunContext) -> Result<Vec<u8>, WallpaperError> {
+			Ok(Vec::new())
+		}
+
+		fn is_installed(&self, program: &str) -> bool {
+			self.0.contains(&program)
+		}
+	}
+
+	fn session(installed: &'static [&'static str]) -> Session {
+		Session {
+			runner: Arc::new(Installed(installed)),
+			..Default::default()
+		}
+	}
+
+	fn missing_tool(result: Result<(), WallpaperError>) -> Option<String> {
+		match result {
+			Ok(()) => None,
+			Err(WallpaperError::MissingTool(missing)) => Some(missing.tool),
+			Err(err) => panic!("unexpected error {err}"),
+		}
+	}
+
+	#[test]
+	fn setters_are_not_required_to_read() {
+		assert_eq!(
+			missing_tool(check(&session(&[]), Environment::Sway)).as_deref(),
+			Some("swaymsg")
+		);
+		assert_eq!(missing_tool(check(&session(&["swaymsg"]), Environment::Sway)), None);
+		assert_eq!(missing_tool(check(&session(&[]), Environment::Kde)), None);
+		assert_eq!(
+			missing_tool(check(&session(&[]), Environment::Xfce)).as_deref(),
+			Some("xfconf-query")
+		);
+	}
+
+	#[test]
+	fn setters_are_required_to_set() {
+		// the X server is not checked while setting, so only the programs are reported
+		let cases: &[(Environment, &'static [&'static str], Option<&str>)] = &[
+			(Environment::X11, &[], Some("xwallpaper")),
+			(Environment::X11, &["xwallpaper"], None),
+			(Environment::Cinnamon, &["xwallpaper"], Some("dconf")),
+			(Environment::Cinnamon, &["dconf"], Some("xwallpaper")),
+			(Environment::Cinnamon, &["dconf", "xwallpaper"], None),
+			(Environment::Sway, &[], Some("swaymsg")),
+			(Environment::Kde, &[], None),
+		];
+		for (environment, installed, tool) in cases {
+			assert_eq!(
+				missing_tool(check_set(&session(installed), *environment)).as_deref(),
+				*tool,
+				"{environment}"
+			);
+		}
+	}
+
+	#[test]
+	fn missing_includes_setters_and_optional() {
+		let tools: Vec<String> = missing(&session(&["dconf"]), Environment::Cinnamon)
+			.into_iter()
+			.map(|missing| missing.tool)
+			.collect();
+		for tool in ["xwallpaper", "convert", "identify"] {
+			assert!(tools.iter().any(|missing| missing == tool), "{tools:?}");
+		}
+		assert!(!tools.iter().any(|missing| missing == "dconf"), "{tools:?}");
+	}
+}
@@ -0,0 +1,368 @@
+use crate::{backend::Session, error::CommandError, plan, CancelToken, DBusCall, Operation, WallpaperError};
+use rustbus::{
+	connection::{self, ll_conn::force_finish_on_error},
+	MessageBuilder, RpcConn,
+};
+use std::{
+	env,
+	ffi::OsString,
+	fmt,
+	io::Read,
+	os::unix::fs::PermissionsExt,
+	path::Path,
+	process::{Command, Stdio},
+	thread,
+	time::{Duration, Instant},
+};
+
+/// default value of [`BuilderOptions::timeout()`](crate::BuilderOptions::timeout)
+pub(crate) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// interval, in witch running commands and D-Bus calls check for timeout and cancellation
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Options of a single command or D-Bus call, witch are passed to the [`CommandRunner`].
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct RunContext {
+	/// maximum duration, configured by [`BuilderOptions::timeout()`](crate::BuilderOptions::timeout)
+	pub timeout: Duration,
+	/// configured by [`BuilderOptions::cancel_token()`](crate::BuilderOptions::cancel_token)
+	pub cancel: CancelToken,
+}
+
+/// Execute the external programs used by the backends, like `xfconf-query`, `dconf`, `swaymsg` or `xwallpaper`,
+/// and the D-Bus calls to `plasmashell`.
+/// Can be replaced by [`BuilderOptions::runner()`](crate::BuilderOptions::runner),
+/// for example to record and script the commands at tests.
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use more_wallpapers::{
+/// 	CommandRunner, DefaultRunner, RunContext, WallpaperBuilder, WallpaperError,
+/// };
+/// use std::process::Command;
+///
+/// struct Logger;
+///
+/// impl CommandRunner for Logger {
+/// 	fn run(&self, command: Command, context: &RunContext) -> Result<Vec<u8>, WallpaperError> {
+/// 		eprintln!("{command:?}");
+/// 		DefaultRunner.run(command, context)
+/// 	}
+/// }
+///
+/// let builder = WallpaperBuilder::options().runner(Logger).build()?;
+/// # Ok(())}
+/// ```
+pub trait CommandRunner: Send + Sync {
+	/// Execute the command and return its stdout.
+	/// A non zero exit code must be returned as [`CommandError::CommandStatus`].
+	/// If the command does not finish inside the timeout of the `context`,
+	/// it should be killed and [`WallpaperError::Timeout`] returned.
+	/// If the token of the `context` is cancelled, it should be killed and [`WallpaperError::Cancelled`] returned.
+	fn run(&self, command: Command, context: &RunContext) -> Result<Vec<u8>, WallpaperError>;
+
+	/// Call the D-Bus method at the session bus and return the string, witch is replied.
+	/// Used by kde to evaluate scripts at `plasmashell`.
+	/// The default implementation calls the method directly.
+	fn call_dbus(&self, call: &DBusCall, context: &RunContext) -> Result<String, WallpaperError> {
+		session_bus_call(call, context)
+	}
+
+	/// Check if the program can be executed. Used to report missing programs before they are needed.
+	/// The default implementation assumes, that all programs are available.
+	fn is_installed(&self, _program: &str) -> bool {
+		true
+	}
+}
+
+impl fmt::Debug for dyn CommandRunner {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("CommandRunner")
+	}
+}
+
+/// Execute the commands directly. This is the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultRunner;
+
+impl CommandRunner for DefaultRunner {
+	fn run(&self, mut command: Command, context: &RunContext) -> Result<Vec<u8>, WallpaperError> {
+		let start = Instant::now();
+		let mut child = command
+			.stdin(Stdio::null())
+			.stdout(Stdio::piped())
+			.stderr(Stdio::piped())
+			.spawn()
+			.map_err(|err| CommandError::CommandIO(command.get_program().into(), err))?;
+		// the pipes are read by threads, so the child does not block if a pipe is full
+		let stdout = read_pipe(child.stdout.take());
+		let stderr = read_pipe(child.stderr.take());
+		let status = loop {
+			let status = child
+				.try_wait()
+				.map_err(|err| CommandError::CommandIO(command.get_program().into(), err))?;
+			if let Some(status) = status {
+				break status;
+			}
+			let cancelled = context.cancel.is_cancelled();
+			if cancelled || start.elapsed() >= context.timeout {
+				// the reader threads are not joined, because the pipes can be kept open by children of the killed program
+				let _ = child.kill();
+				let _ = child.wait();
+				if cancelled {
+					return Err(WallpaperError::Cancelled);
+				}
+				return Err(WallpaperError::Timeout {
+					program: command.get_program().to_string_lossy().into_owned(),
+					elapsed: start.elapsed(),
+				});
+			}
+			thread::sleep(POLL_INTERVAL);
+		};
+		let stdout = stdout.join().unwrap_or_default();
+		let stderr = stderr.join().unwrap_or_default();
+		if !status.success() {
+			return Err(CommandError::CommandStatus {
+				command: Box::new(command),
+				exit_code: status.code(),
+				stderr,
+			}
+			.into());
+		}
+		Ok(stdout)
+	}
+
+	/// search the program at PATH
+	fn is_installed(&self, program: &str) -> bool {
+		if program.contains('/') {
+			return is_executable(Path::new(program));
+		}
+		env::var_os("PATH")
+			.map(|path| env::split_paths(&path).any(|dir| is_executable(&dir.join(program))))
+			.unwrap_or(false)
+	}
+}
+
+fn is_executable(path: &Path) -> bool {
+	path.metadata()
+		.map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+		.unwrap_or(false)
+}
+
+/// read the whole pipe at a new thread
+fn read_pipe<R>(pipe: Option<R>) -> thread::JoinHandle<Vec<u8>>
+where
+	R: Read + Send + 'static,
+{
+	thread::spawn(move || {
+		let mut buf = Vec::new();
+		if let Some(mut pipe) = pipe {
+			let _ = pipe.read_to_end(&mut buf);
+		}
+		buf
+	})
+}
+
+/// call the method at the session bus and wait for the reply
+fn session_bus_call(call: &DBusCall, context: &RunContext) -> Result<String, WallpaperError> {
+	let start = Instant::now();
+	let timed_out = |err| match err {
+		connection::Error::TimedOut => WallpaperError::Timeout {
+			program: call.destination.clone(),
+			elapsed: start.elapsed(),
+		},
+		err => err.into(),
+	};
+	let session_path = rustbus::get_session_bus_path()?;
+	let mut con =
+		RpcConn::connect_to_path(session_path, connection::Timeout::Duration(context.timeout)).map_err(timed_out)?;
+	let mut message = MessageBuilder::new()
+		.call(call.member.as_str())
+		.with_interface(call.interface.as_str())
+		.on(call.path.as_str())
+		.at(call.destination.as_str())
+		.build();
+	for arg in &call.args {
+		message.body.push_param(arg.as_str()).map_err(connection::Error::from)?;
+	}
+	let id = con.send_message(&mut message)?.write_all().map_err(force_finish_on_error)?;
+	// the response is awaited in short intervals, so a cancellation is noticed
+	let reply = loop {
+		if context.cancel.is_cancelled() {
+			return Err(WallpaperError::Cancelled);
+		}
+		let left = context.timeout.saturating_sub(start.elapsed());
+		if left.is_zero() {
+			return Err(timed_out(connection::Error::TimedOut));
+		}
+		match con.wait_response(id, connection::Timeout::Duration(left.min(POLL_INTERVAL))) {
+			Err(connection::Error::TimedOut) => continue,
+			reply => break reply?,
+		}
+	};
+	let reply = reply
+		.body
+		.parser()
+		.get::<&str>()
+		.map_err(|err| WallpaperError::InvalidOutput {
+			program: call.destination.clone(),
+			error: err.into(),
+		})?;
+	Ok(reply.to_owned())
+}
+
+/// Execute the commands at the host by `flatpak-spawn --host`, so they can be used from inside a flatpak sandbox.
+/// This requires the `--talk-name=org.freedesktop.Flatpak` permission.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FlatpakRunner;
+
+impl CommandRunner for FlatpakRunner {
+	fn run(&self, command: Command, context: &RunContext) -> Result<Vec<u8>, WallpaperError> {
+		let mut host = Command::new("flatpak-spawn");
+		host.arg("--host");
+		// the environment of the command is not passed to the host automatically
+		for (key, value) in command.get_envs() {
+			if let Some(value) = value {
+				let mut env = OsString::from("--env=");
+				env.push(key);
+				env.push("=");
+				env.push(value);
+				host.arg(env);
+			}
+		}
+		host.arg(command.get_program()).args(command.get_args());
+		DefaultRunner.run(host, context)
+	}
+}
+
+impl Session {
+	/// options of the commands and D-Bus calls, witch are passed to the [`CommandRunner`]
+	pub fn run_context(&self) -> RunContext {
+		RunContext {
+			timeout: self.timeout,
+			cancel: self.cancel.clone(),
+		}
+	}
+
+	/// Execute the command with the [`CommandRunner`] of the builder and return its stdout.
+	/// Unlike the built-in backends, the command is also executed, if a plan is created.
+	pub fn run(&self, command: Command) -> Result<Vec<u8>, WallpaperError> {
+		run_command(self, command)
+	}
+
+	/// call the D-Bus method with the [`CommandRunner`] of the builder
+	pub fn call_dbus(&self, call: &DBusCall) -> Result<String, WallpaperError> {
+		call_dbus(self, call)
+	}
+}
+
+/// check with the runner of the session, if the program is installed
+pub(super) fn is_installed(session: &Session, program: &str) -> bool {
+	session.runner.is_installed(program)
+}
+
+/// execute the command with the runner of the session
+pub(super) fn run_command(session: &Session, command: Command) -> Result<Vec<u8>, WallpaperError> {
+	#[cfg(feature = "tracing")]
+	let _span = tracing::debug_span!("run_command", program = ?command.get_program()).entered();
+	#[cfg(feature = "tracing")]
+	tracing::debug!(args = ?command.get_args().collect::<Vec<_>>(), "execute command");
+	let result = session.runner.run(command, &session.run_context());
+	#[cfg(feature = "tracing")]
+	match &result {
+		Ok(_) => tracing::debug!(exit_code = 0, "command finished"),
+		Err(WallpaperError::Command(CommandError::CommandStatus { exit_code, .. })) => {
+			tracing::debug!(?exit_code, "command failed")
+		},
+		Err(err) => tracing::debug!(error = %err, "command failed"),
+	}
+	result
+}
+
+/// Execute a command, witch changes the wallpaper.
+/// If a plan is created, the command is only recorded.
+pub(super) fn apply_command(session: &Session, command: Command) -> Result<Vec<u8>, WallpaperError> {
+	let recorded = plan::record(|| Operation::Command {
+		program: command.get_program().to_string_lossy().into_owned(),
+		args: command.get_args().map(|arg| arg.to_string_lossy().into_owned()).collect(),
+	});
+	if recorded {
+		return Ok(Vec::new());
+	}
+	run_command(session, command)
+}
+
+/// call the D-Bus method with the runner of the session
+pub(super) fn call_dbus(session: &Session, call: &DBusCall) -> Result<String, WallpaperError> {
+	#[cfg(feature = "tracing")]
+	tracing::debug!(%call, "call D-Bus method");
+	session.runner.call_dbus(call, &session.run_context())
+}
+
+/// Call a D-Bus method, witch changes the wallpaper.
+/// If a plan is created, the call is only recorded.
+pub(super) fn apply_dbus(session: &Session, call: DBusCall) -> Result<String, WallpaperError> {
+	let recorded = plan::record(|| Operation::DBus(call.clone()));
+	if recorded {
+		return Ok(String::new());
+	}
+	call_dbus(session, &call)
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+	use super::*;
+	use crate::Screen;
+	use std::sync::Arc;
+
+	/// runner, witch returns the same output for every command and D-Bus call
+	struct Scripted(&'static [u8]);
+
+	impl CommandRunner for Scripted {
+		fn run(&self, _command: Command, _context: &RunContext) -> Result<Vec<u8>, WallpaperError> {
+			Ok(self.0.to_vec())
+		}
+
+		fn call_dbus(&self, _call: &DBusCall, _context: &RunContext) -> Result<String, WallpaperError> {
+			Ok(String::from_utf8_lossy(self.0).into_owned())
+		}
+	}
+
+	/// session, witch answers every command and D-Bus call with `output`
+	pub(in crate::linux) fn scripted(output: &'static [u8]) -> Session {
+		Session {
+			runner: Arc::new(Scripted(output)),
+			..Default::default()
+		}
+	}
+
+	/// active screen without background and mode
+	pub(in crate::linux) fn screen(name: &str) -> Screen {
+		Screen {
+			name: name.to_owned(),
+			background: None,
+			mode: None,
+			fill_color: None,
+			focal_point: None,
+			active: true,
+			primary: false,
+			geometry: None,
+		}
+	}
+
+	#[test]
+	fn cancelled_command_is_killed() {
+		let context = RunContext {
+			timeout: DEFAULT_TIMEOUT,
+			cancel: CancelToken::new(),
+		};
+		context.cancel.cancel();
+		let mut command = Command::new("sleep");
+		command.arg("10");
+		let start = Instant::now();
+		let result = DefaultRunner.run(command, &context);
+		assert!(matches!(result, Err(WallpaperError::Cancelled)));
+		assert!(start.elapsed() < Duration::from_secs(5));
+	}
+}
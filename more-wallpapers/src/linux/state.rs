@@ -0,0 +1,182 @@
+//! Record of the wallpapers applied by this crate.
+//! Several environments can not report the current wallpaper,
+//! so the recorded values are used instead.
+//!
+//! The records are stored at `$XDG_STATE_HOME/more-wallpapers/state.json`
+//! and are grouped by environment and screen name.
+
+use super::{create_xdg_dir, xdg_dir};
+use crate::{error::Context, Background, Color, Environment, Mode, Screen, WallpaperError};
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::BTreeMap,
+	env, fs, io, process,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Record {
+	background: Option<Background>,
+	mode: Option<Mode>,
+	fill_color: Option<Color>,
+	/// time, when the wallpaper was applied, in seconds since the unix epoch
+	timestamp: u64,
+	/// name of the program, witch has applied the wallpaper
+	source: String,
+}
+
+/// records of all screens, grouped by environment
+type State = BTreeMap<String, BTreeMap<String, Record>>;
+
+/// path of the state file, `create` does create its directory, if it does not exist
+fn state_path(create: bool) -> Result<Utf8PathBuf, WallpaperError> {
+	let dir = if create {
+		create_xdg_dir("XDG_STATE_HOME", ".local/state")?
+	} else {
+		xdg_dir("XDG_STATE_HOME", ".local/state")?
+	};
+	Ok(dir.join("state.json"))
+}
+
+fn load_state(path: &Utf8Path) -> Result<State, WallpaperError> {
+	match fs::read_to_string(path) {
+		Ok(state) => Ok(serde_json::from_str(&state)?),
+		Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(State::default()),
+		Err(err) => Err(err).context(path),
+	}
+}
+
+/// Fill the current wallpaper of the `screens` with the recorded values,
+/// if the environment has not reported the wallpaper.
+/// The record is only a fallback, so a missing or corrupt state file is ignored.
+pub(super) fn restore(environment: Environment, screens: &mut [Screen]) {
+	let state = match state_path(false).and_then(|path| load_state(&path)) {
+		Ok(state) => state,
+		#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+		Err(err) => {
+			#[cfg(feature = "tracing")]
+			tracing::warn!(error = %err, "failed to load the recorded wallpapers");
+			return;
+		},
+	};
+	restore_from(state, environment, screens);
+}
+
+/// fill the screens without wallpaper with the records of the `state`
+fn restore_from(mut state: State, environment: Environment, screens: &mut [Screen]) {
+	let Some(records) = state.get_mut(&environment.to_string()) else {
+		return;
+	};
+	for screen in screens.iter_mut().filter(|screen| screen.background.is_none()) {
+		if let Some(record) = records.remove(&screen.name) {
+			screen.background = record.background;
+			screen.mode = record.mode;
+			screen.fill_color = record.fill_color;
+		}
+	}
+}
+
+/// Record the wallpaper of the `screens`.
+/// A corrupt state file is replaced.
+pub(super) fn save(environment: Environment, screens: &[Screen]) -> Result<(), WallpaperError> {
+	save_to(&state_path(true)?, environment, screens)
+}
+
+/// record the wallpaper of the `screens` at the state file `path`
+fn save_to(path: &Utf8Path, environment: Environment, screens: &[Screen]) -> Result<(), WallpaperError> {
+	let mut state = load_state(path).unwrap_or_default();
+	let timestamp = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|duration| duration.as_secs())
+		.unwrap_or_default();
+	let source = env::current_exe()
+		.ok()
+		.and_then(|exe| exe.file_name().map(|name| name.to_string_lossy().into_owned()))
+		.unwrap_or_default();
+	let records = state.entry(environment.to_string()).or_default();
+	for screen in screens {
+		records.insert(screen.name.clone(), Record {
+			background: screen.background.clone(),
+			mode: screen.mode,
+			fill_color: screen.fill_color,
+			timestamp,
+			source: source.clone(),
+		});
+	}
+	// write a temporary file and rename it afterwards,
+	// so a interrupted write or a concurrent process can not leave a truncated state file behind
+	let temp = path.with_file_name(format!("state.json.{}.tmp", process::id()));
+	fs::write(&temp, serde_json::to_string_pretty(&state)?).context(&temp)?;
+	fs::rename(&temp, path)
+		.map_err(|err| {
+			let _ = fs::remove_file(&temp);
+			err
+		})
+		.context(path)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::linux::runner::tests::screen;
+
+	/// screens are compared by their serialized form, because they do not implement `PartialEq`
+	fn values(screens: &[Screen]) -> serde_json::Value {
+		serde_json::to_value(screens).unwrap()
+	}
+
+	#[test]
+	fn recorded_wallpapers_are_restored() {
+		let dir = Utf8PathBuf::from_path_buf(env::temp_dir())
+			.unwrap()
+			.join(format!("more-wallpapers-state-test-{}", process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		let path = dir.join("state.json");
+		let mut recorded = screen("DP-1");
+		recorded.background = Some(Background::Image("/wallpaper.jpg".into()));
+		recorded.mode = Some(Mode::Crop);
+		recorded.fill_color = Some(Color::WHITE);
+		save_to(&path, Environment::Sway, &[recorded.clone()]).unwrap();
+		// a second record must keep the first one
+		let mut other = screen("DP-2");
+		other.background = Some(Background::Color(Color::BLACK));
+		other.mode = Some(Mode::Stretch);
+		save_to(&path, Environment::Sway, &[other.clone()]).unwrap();
+		let state = load_state(&path);
+		fs::remove_dir_all(&dir).unwrap();
+		let state = state.unwrap();
+
+		let mut screens = vec![screen("DP-1"), screen("DP-2"), screen("HDMI-1")];
+		restore_from(state, Environment::Sway, &mut screens);
+		assert_eq!(values(&screens), values(&[recorded, other, screen("HDMI-1")]));
+	}
+
+	#[test]
+	fn reported_wallpapers_are_not_overwritten() {
+		let mut recorded = screen("DP-1");
+		recorded.background = Some(Background::Image("/recorded.jpg".into()));
+		let mut state = State::default();
+		state
+			.entry(Environment::Sway.to_string())
+			.or_default()
+			.insert("DP-1".to_owned(), Record {
+				background: recorded.background.clone(),
+				mode: Some(Mode::Crop),
+				fill_color: None,
+				timestamp: 0,
+				source: String::new(),
+			});
+		let mut reported = screen("DP-1");
+		reported.background = Some(Background::Image("/reported.jpg".into()));
+		let mut screens = vec![reported.clone()];
+		restore_from(state, Environment::Sway, &mut screens);
+		assert_eq!(values(&screens), values(&[reported]));
+	}
+
+	#[test]
+	fn missing_state_file_is_empty() {
+		let state = load_state(Utf8Path::new("/does/not/exist/state.json")).unwrap();
+		assert!(state.is_empty());
+	}
+}
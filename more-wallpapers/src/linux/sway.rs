@@ -1,5 +1,5 @@
-use super::run_command;
-use crate::{error::WallpaperError, Mode, Screen};
+use super::{apply_command, render, run_command, utf8_output};
+use crate::{backend::Session, error::WallpaperError, Background, Mode, Rectangle, Screen};
 use serde::Deserialize;
 use std::process::Command;
 use strum_macros::{Display, EnumString};
@@ -19,8 +19,8 @@ impl From<Mode> for SMode {
 		match value {
 			Mode::Center => Self::Center,
 			Mode::Crop => Self::Fill,
-			Mode::Fit => Self::Fit,
-			Mode::Stretch => Self::Stretch,
+			Mode::BlurFill | Mode::Fit => Self::Fit,
+			Mode::Span | Mode::Stretch => Self::Stretch,
 			Mode::Tile => Self::Tile,
 		}
 	}
@@ -38,41 +38,115 @@ impl From<SMode> for Mode {
 	}
 }
 
+#[derive(Deserialize, Debug)]
+struct OutputRect {
+	x: i32,
+	y: i32,
+	width: u32,
+	height: u32,
+}
+
 #[derive(Deserialize, Debug)]
 struct OutputScreens {
 	name: String,
 	active: bool,
+	#[serde(default)]
+	focused: bool,
+	rect: Option<OutputRect>,
 }
 
-pub(crate) fn get_screens() -> Result<Vec<Screen>, WallpaperError> {
+pub(crate) fn get_screens(session: &Session) -> Result<Vec<Screen>, WallpaperError> {
 	let mut command = Command::new("swaymsg");
 	command.args(["-t", "get_outputs"]);
-	let output = run_command(command)?;
-	let output = String::from_utf8(output).unwrap();
-	println!("{output}");
-	let output: Vec<OutputScreens> = serde_json::from_str(&output)?;
-	println!("{output:#?}");
+	parse_outputs(run_command(session, command)?)
+}
+
+/// parse the output of `swaymsg -t get_outputs`
+fn parse_outputs(output: Vec<u8>) -> Result<Vec<Screen>, WallpaperError> {
+	let output = utf8_output("swaymsg", output)?;
+	let output: Vec<OutputScreens> = serde_json::from_str(&output).map_err(|err| WallpaperError::InvalidOutput {
+		program: "swaymsg".to_owned(),
+		error: err.into(),
+	})?;
 	Ok(output
 		.into_iter()
 		.map(|screen| Screen {
 			name: screen.name,
-			wallpaper: None,
+			background: None,
 			mode: None,
+			fill_color: None,
+			focal_point: None,
 			active: screen.active,
+			// sway has no primary output, so the focused one is used instead
+			primary: screen.focused,
+			geometry: screen.rect.filter(|_| screen.active).map(|rect| Rectangle {
+				x: rect.x,
+				y: rect.y,
+				width: rect.width,
+				height: rect.height,
+			}),
 		})
 		.collect())
 }
 
-pub(crate) fn set_screens(screens: Vec<Screen>) -> Result<(), WallpaperError> {
-	for screen in screens {
-		let mut command = Command::new("swaymsg");
-		command
-			.arg("output")
-			.arg(screen.name)
-			.arg("bg")
-			.arg(screen.wallpaper.unwrap())
-			.arg(format!("{}", SMode::from(screen.mode.unwrap())));
-		run_command(command)?;
+pub(crate) fn set_screen(session: &Session, screen: Screen) -> Result<(), WallpaperError> {
+	let mut command = Command::new("swaymsg");
+	command.arg("output").arg(&screen.name).arg("bg");
+	match screen.require_background()? {
+		Background::Color(color) => {
+			command.arg(color.to_string()).arg("solid_color");
+		},
+		Background::Image(wallpaper) => {
+			command.arg(wallpaper).arg(SMode::from(screen.require_mode()?).to_string());
+			if let Some(color) = screen.fill_color {
+				command.arg(color.to_string());
+			}
+		},
+		background @ Background::Gradient { .. } => {
+			command
+				.arg(render::background_image(session, background)?)
+				.arg(SMode::Stretch.to_string());
+		},
 	}
+	apply_command(session, command)?;
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_outputs() {
+		let screens = super::parse_outputs(
+			br#"[{"name": "DP-1", "active": true, "focused": true, "rect": {"x": 0, "y": 0, "width": 1920, "height": 1080}},
+			{"name": "HDMI-A-1", "active": false, "rect": {"x": 0, "y": 0, "width": 0, "height": 0}}]"#
+				.to_vec(),
+		)
+		.unwrap();
+		assert_eq!(screens.len(), 2);
+		assert!(screens[0].primary);
+		assert_eq!(screens[0].geometry.map(|geometry| geometry.width), Some(1920));
+		assert_eq!(screens[1].geometry, None);
+	}
+
+	#[test]
+	fn malformed_outputs() {
+		let outputs: &[&[u8]] = &[
+			b"",
+			b"\xff\xfe",
+			b"not json",
+			b"[{}]",
+			br#"[{"name": "DP-1"}]"#,
+			br#"[{"name": "DP-1", "active": true, "rect": {"x": -1}}]"#,
+		];
+		for output in outputs {
+			let result = super::parse_outputs(output.to_vec());
+			assert!(
+				matches!(result, Err(WallpaperError::InvalidOutput { .. })),
+				"{:?}: {result:?}",
+				String::from_utf8_lossy(output)
+			);
+		}
+	}
+}
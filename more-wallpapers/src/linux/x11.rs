@@ -1,4 +1,8 @@
-use crate::{error::CommandError, linux::run_command, Mode, Screen};
+use crate::{
+	backend::Session,
+	linux::{apply_command, render},
+	Mode, Rectangle, Screen, WallpaperError,
+};
 use std::process::Command;
 
 pub(crate) fn get_screens() -> Result<Vec<Screen>, xrandr::XrandrError> {
@@ -9,31 +13,66 @@ pub(crate) fn get_screens() -> Result<Vec<Screen>, xrandr::XrandrError> {
 			screens.push(Screen {
 				name: monitor.name,
 				active: true,
-				wallpaper: None,
+				background: None,
 				mode: None,
+				fill_color: None,
+				focal_point: None,
+				primary: monitor.is_primary,
+				geometry: Some(Rectangle {
+					x: monitor.x,
+					y: monitor.y,
+					width: monitor.width_px as u32,
+					height: monitor.height_px as u32,
+				}),
 			})
 		}
 	}
 	Ok(screens)
 }
 
-pub(crate) fn set_screens(screens: Vec<Screen>) -> Result<(), CommandError> {
+pub(crate) fn set_screens(session: &Session, screens: Vec<Screen>) -> Result<(), WallpaperError> {
 	let mut command = Command::new("xwallpaper");
-	for screen in screens {
-		let mode = match screen.mode.unwrap() {
-			Mode::Center => "center",
-			Mode::Crop => "zoom",
-			Mode::Fit => "maximize",
-			Mode::Stretch => "stretch",
-			Mode::Tile => "tile",
+	// xwallpaper can span a wallpaper natively by using the root window,
+	// but this only works if all screens use the same spanned wallpaper
+	let span_background = screens
+		.first()
+		.and_then(|first| first.background.as_ref())
+		.filter(|background| {
+			screens
+				.iter()
+				.all(|screen| screen.mode == Some(Mode::Span) && screen.background.as_ref() == Some(*background))
+		});
+	if let Some(background) = span_background {
+		let wallpaper = render::background_image(session, background)?;
+		command.args(["--no-randr", "--zoom", wallpaper.as_str()]);
+		apply_command(session, command)?;
+		return Ok(());
+	}
+	for screen in &screens {
+		let background = screen.require_background()?;
+		let mut wallpaper = render::background_image(session, background)?;
+		let mode = if background.image().is_none() {
+			// xwallpaper does not support colors, so the rendered image must fill the screen
+			"stretch"
+		} else if let Some(filled) = render::fill(session, &wallpaper, screen)? {
+			// xwallpaper does not support fill colors, so the rendered image must fill the screen
+			wallpaper = filled;
+			"stretch"
+		} else {
+			match screen.require_mode()? {
+				Mode::Center => "center",
+				Mode::Crop => "zoom",
+				Mode::BlurFill | Mode::Fit => "maximize",
+				Mode::Span => {
+					wallpaper = render::span(session, &wallpaper, screen, &screens)?;
+					"stretch"
+				},
+				Mode::Stretch => "stretch",
+				Mode::Tile => "tile",
+			}
 		};
-		command.args([
-			"--output",
-			&screen.name,
-			&format!("--{mode}"),
-			screen.wallpaper.as_ref().unwrap().as_str(),
-		]);
+		command.args(["--output", &screen.name, &format!("--{mode}"), wallpaper.as_str()]);
 	}
-	run_command(command)?;
+	apply_command(session, command)?;
 	Ok(())
 }
@@ -1,21 +1,53 @@
-use super::run_command;
-use crate::{Mode, Screen, WallpaperError};
-use std::{collections::HashMap, ffi::OsStr, process::Command};
+use super::{apply_command, run_command, utf8_output, x11};
+use crate::{backend::Session, Background, Color, GradientDirection, Mode, Screen, WallpaperError};
+use std::{collections::BTreeMap, process::Command};
 
-pub(crate) fn get_screens() -> Result<Vec<Screen>, WallpaperError> {
-	fn load_property(property: &str) -> Result<String, WallpaperError> {
-		let mut command = Command::new("xfconf-query");
-		command.args(["--channel", "xfce4-desktop", "-p"]);
-		command.arg(format!("/backdrop/{property}"));
-		let output = run_command(command)?;
-		let output = String::from_utf8(output).unwrap();
-		Ok(output)
-	}
+/// raw values of the xfconf properties of one screen
+#[derive(Default)]
+struct Properties {
+	last_image: Option<String>,
+	image_style: Option<String>,
+	color_style: Option<String>,
+	rgba1: Option<String>,
+	rgba2: Option<String>,
+}
 
-	let mut command = Command::new("xfconf-query");
-	command.args(["--channel", "xfce4-desktop", "--list"]);
-	let output = run_command(command)?;
-	let output = String::from_utf8(output).unwrap();
+/// parse a color from the output of xfconf-query, witch looks like the following:
+///
+///	Value is an array with 4 items:
+///
+///	0.000000
+///	0.168627
+///	0.211765
+///	1.000000
+fn parse_color(value: &str) -> Option<Color> {
+	let channels: Vec<f64> = value.lines().filter_map(|line| line.trim().parse().ok()).collect();
+	let channel = |i: usize| channels.get(i).map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8);
+	Some(Color::new(channel(0)?, channel(1)?, channel(2)?))
+}
+
+/// format a color as arguments for xfconf-query
+fn color_args(color: Color) -> Vec<(&'static str, String)> {
+	[color.red, color.green, color.blue]
+		.into_iter()
+		.map(|channel| ("double", format!("{:.6}", channel as f64 / 255.0)))
+		.chain([("double", "1.000000".to_owned())])
+		.collect()
+}
+
+/// backdrop property of a screen, listed by xfconf-query
+struct BackdropProperty<'a> {
+	/// path of the property relative to `/backdrop/`
+	path: &'a str,
+	screen: String,
+	/// xrandr output name of the screen
+	output: Option<&'a str>,
+	key: &'a str,
+	active: bool,
+}
+
+/// parse the output of `xfconf-query --list` and return the known backdrop properties
+fn parse_list(output: &str) -> Vec<BackdropProperty<'_>> {
 	//	the outpult looks like the following:
 	//
 	//	/backdrop/screen0/monitor0/image-style
@@ -27,7 +59,7 @@ pub(crate) fn get_screens() -> Result<Vec<Screen>, WallpaperError> {
 	//	/backdrop/screen0/monitorVirtual-1/workspace1/color-style
 	//	/backdrop/screen0/monitorVirtual-1/workspace1/image-style
 	//	/backdrop/screen0/monitorVirtual-1/workspace1/last-image
-	let mut screens: HashMap<String, Screen> = Default::default();
+	let mut properties = Vec::new();
 	for line in output.lines().filter_map(|s| s.strip_prefix("/backdrop/")) {
 		let mut split = line.split('/');
 		let first = split.next();
@@ -42,67 +74,193 @@ pub(crate) fn get_screens() -> Result<Vec<Screen>, WallpaperError> {
 			//to short -> wrong key
 			continue;
 		};
-		let (screen_name, key_type, active) = if let Some(fourth) = fourth {
+		let (screen, key, active) = if let Some(fourth) = fourth {
 			// if name exist out of  part, the screen is active.
 			// Otherwise it is default for new workspaces
 			(format!("{}/{}/{}", first, second, third), fourth, true)
 		} else {
 			(format!("{}/{}", first, second), third, false)
 		};
-		if !(key_type == "last-image" || key_type == "image-style") {
+		if !["last-image", "image-style", "color-style", "rgba1", "rgba2"].contains(&key) {
 			// wrong key
 			continue;
 		}
-		let value = load_property(line)?;
-		let screen = screens.entry(screen_name.clone()).or_insert_with(|| Screen {
-			name: screen_name,
-			wallpaper: None,
-			mode: None,
+		properties.push(BackdropProperty {
+			path: line,
+			screen,
+			output: second.strip_prefix("monitor"),
+			key,
 			active,
 		});
-		if key_type == "last-image" {
-			screen.wallpaper = Some(value.into());
+	}
+	properties
+}
+
+pub(crate) fn get_screens(session: &Session) -> Result<Vec<Screen>, WallpaperError> {
+	fn load_property(session: &Session, property: &str) -> Result<String, WallpaperError> {
+		let mut command = Command::new("xfconf-query");
+		// avoid locale specific decimal separator
+		command.env("LC_ALL", "C");
+		command.args(["--channel", "xfce4-desktop", "-p"]);
+		command.arg(format!("/backdrop/{property}"));
+		let output = run_command(session, command)?;
+		utf8_output("xfconf-query", output)
+	}
+
+	let mut command = Command::new("xfconf-query");
+	command.args(["--channel", "xfce4-desktop", "--list"]);
+	let output = run_command(session, command)?;
+	let output = utf8_output("xfconf-query", output)?;
+	// xfce does use the xrandr output names, so position and primary screen can be looked up by xrandr.
+	// This is only additional information, so errors are ignored.
+	let monitors = x11::get_screens().unwrap_or_default();
+	let mut screens: BTreeMap<String, (Screen, Properties)> = Default::default();
+	for property in parse_list(&output) {
+		let value = load_property(session, property.path)?;
+		let monitor = property
+			.output
+			.and_then(|output| monitors.iter().find(|monitor| monitor.name == output))
+			.filter(|_| property.active);
+		let active = property.active;
+		let (_, properties) = screens.entry(property.screen.clone()).or_insert_with(|| {
+			let screen = Screen {
+				name: property.screen,
+				background: None,
+				mode: None,
+				fill_color: None,
+				focal_point: None,
+				active,
+				primary: monitor.map(|monitor| monitor.primary).unwrap_or(false),
+				geometry: monitor.and_then(|monitor| monitor.geometry),
+			};
+			(screen, Properties::default())
+		});
+		let property = match property.key {
+			"last-image" => &mut properties.last_image,
+			"image-style" => &mut properties.image_style,
+			"color-style" => &mut properties.color_style,
+			"rgba1" => &mut properties.rgba1,
+			_ => &mut properties.rgba2,
+		};
+		*property = Some(value);
+	}
+	let mut result = Vec::new();
+	for (mut screen, properties) in screens.into_values() {
+		let image_style = properties.image_style.as_deref().map(str::trim_end);
+		if image_style == Some("0") {
+			//single color background is used instead of a image
+			let start = properties.rgba1.as_deref().and_then(parse_color);
+			let end = properties.rgba2.as_deref().and_then(parse_color);
+			let direction = match properties.color_style.as_deref().map(str::trim_end) {
+				Some("1") => Some(GradientDirection::Horizontal),
+				Some("2") => Some(GradientDirection::Vertical),
+				_ => None,
+			};
+			screen.background = Background::from_colors(direction, start, end);
 		} else {
-			let mode = match value.trim_end() {
-				"0" => None, //single color background is used instead of a image
-				"1" => Some(Mode::Center),
-				"2" => Some(Mode::Tile),
-				"3" => Some(Mode::Stretch),
-				"4" => Some(Mode::Fit),
-				"5" => Some(Mode::Crop),
-				_ => return Err(WallpaperError::UnknownMode(value)),
+			if properties.color_style.as_deref().map(str::trim_end) == Some("0") {
+				screen.fill_color = properties.rgba1.as_deref().and_then(parse_color);
+			}
+			screen.background = properties
+				.last_image
+				.map(|last_image| Background::Image(last_image.trim_end().into()));
+			screen.mode = match image_style {
+				None => None,
+				Some("1") => Some(Mode::Center),
+				Some("2") => Some(Mode::Tile),
+				Some("3") => Some(Mode::Stretch),
+				Some("4") => Some(Mode::Fit),
+				Some("5") => Some(Mode::Crop),
+				Some("6") => Some(Mode::Span),
+				Some(value) => return Err(WallpaperError::UnknownMode(value.to_owned())),
 			};
-			screen.mode = mode;
 		}
+		result.push(screen);
 	}
-	Ok(screens.into_values().collect())
+	Ok(result)
 }
 
-pub(crate) fn set_screens(screens: Vec<Screen>) -> Result<(), WallpaperError> {
-	fn set_key<P: AsRef<OsStr>>(key: &str, property: P) -> Result<(), WallpaperError> {
-		let mut command = Command::new("xfconf-query");
-		command
-			.args(["--channel", "xfce4-desktop", "-p"])
-			.arg(key)
-			.arg("-s")
-			.arg(property);
-		run_command(command)?;
-		Ok(())
+fn set_key(session: &Session, key: &str, values: &[(&str, String)]) -> Result<(), WallpaperError> {
+	let mut command = Command::new("xfconf-query");
+	command.env("LC_ALL", "C");
+	command.args(["--channel", "xfce4-desktop", "-p"]).arg(key).arg("--create");
+	for (value_type, value) in values {
+		command.args(["-t", value_type, "-s", value]);
 	}
+	apply_command(session, command)?;
+	Ok(())
+}
 
+pub(crate) fn set_screen(session: &Session, screen: Screen) -> Result<(), WallpaperError> {
 	//set_key("/backdrop/single-workspace-mode", "false")?; //force different wallpaper per workscreen
-	for screen in screens {
-		let key = format!("/backdrop/{}/last-image", screen.name);
-		set_key(&key, &screen.wallpaper.unwrap())?;
-		let mode: u8 = match screen.mode.unwrap() {
-			Mode::Center => 1,
-			Mode::Tile => 2,
-			Mode::Stretch => 3,
-			Mode::Fit => 4,
-			Mode::Crop => 5,
-		};
-		let key = format!("/backdrop/{}/image-style", screen.name);
-		set_key(&key, format!("{mode}"))?;
+	let key = |property: &str| format!("/backdrop/{}/{property}", screen.name);
+	let (color_style, colors) = match screen.require_background()? {
+		Background::Image(wallpaper) => {
+			set_key(session, &key("last-image"), &[("string", wallpaper.to_string())])?;
+			let mode: u8 = match screen.require_mode()? {
+				Mode::Center => 1,
+				Mode::Tile => 2,
+				Mode::Stretch => 3,
+				Mode::BlurFill | Mode::Fit => 4,
+				Mode::Crop => 5,
+				Mode::Span => 6,
+			};
+			set_key(session, &key("image-style"), &[("int", mode.to_string())])?;
+			if let Some(color) = screen.fill_color {
+				set_key(session, &key("color-style"), &[("int", "0".to_owned())])?;
+				set_key(session, &key("rgba1"), &color_args(color))?;
+			}
+			return Ok(());
+		},
+		Background::Color(color) => (0, vec![*color]),
+		Background::Gradient { direction, start, end } => {
+			let color_style = match direction {
+				GradientDirection::Horizontal => 1,
+				GradientDirection::Vertical => 2,
+			};
+			(color_style, vec![*start, *end])
+		},
+	};
+	// image style 0 shows only the colors
+	set_key(session, &key("image-style"), &[("int", "0".to_owned())])?;
+	set_key(session, &key("color-style"), &[("int", color_style.to_string())])?;
+	for (i, color) in colors.into_iter().enumerate() {
+		set_key(session, &key(&format!("rgba{}", i + 1)), &color_args(color))?;
 	}
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::linux::runner::tests::scripted;
+
+	#[test]
+	fn parse_list() {
+		let output = "/backdrop/screen0/monitor0/last-image\n\
+		              /backdrop/screen0/monitor0/last-single-image\n\
+		              /backdrop/screen0/monitorDP-1/workspace0/image-style\n\
+		              /backdrop/a/b/c/d/e\n\
+		              /backdrop\n\
+		              /backdrop/screen0\n\
+		              /desktop-icons/style\n";
+		let properties = super::parse_list(output);
+		let properties: Vec<_> = properties
+			.iter()
+			.map(|property| (property.screen.as_str(), property.output, property.key, property.active))
+			.collect();
+		assert_eq!(properties, [
+			("screen0/monitor0", Some("0"), "last-image", false),
+			("screen0/monitorDP-1/workspace0", Some("DP-1"), "image-style", true)
+		]);
+	}
+
+	#[test]
+	fn malformed_output() {
+		let result = get_screens(&scripted(b"\xff\xfe"));
+		assert!(matches!(result, Err(WallpaperError::InvalidOutput { .. })));
+		// every property returns the listed path, witch is no valid image style
+		let result = get_screens(&scripted(b"/backdrop/screen0/monitor0/image-style\n"));
+		assert!(matches!(result, Err(WallpaperError::UnknownMode(_))));
+	}
+}
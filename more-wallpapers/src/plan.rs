@@ -0,0 +1,144 @@
+use crate::{Mode, WallpaperBuilder, WallpaperError};
+use std::{cell::RefCell, fmt};
+
+/// A single operation, witch changes the wallpaper.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Operation {
+	/// execute a program
+	Command { program: String, args: Vec<String> },
+	/// call a D-Bus method
+	DBus(DBusCall),
+	/// set the wallpaper by the [fallback crate](https://crates.io/crates/wallpaper)
+	Fallback { wallpaper: String, mode: Mode },
+}
+
+impl fmt::Display for Operation {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Command { program, args } => {
+				write!(f, "{program}")?;
+				for arg in args {
+					if arg.is_empty() || arg.contains(|char: char| char.is_whitespace() || char == '"' || char == '\'') {
+						write!(f, " {arg:?}")?;
+					} else {
+						write!(f, " {arg}")?;
+					}
+				}
+				Ok(())
+			},
+			Self::DBus(call) => write!(f, "{call}"),
+			Self::Fallback { wallpaper, mode } => write!(f, "set wallpaper {wallpaper:?} with mode {mode}"),
+		}
+	}
+}
+
+/// A D-Bus method call at the session bus, witch takes only string arguments.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DBusCall {
+	pub destination: String,
+	pub path: String,
+	pub interface: String,
+	pub member: String,
+	pub args: Vec<String>,
+}
+
+impl fmt::Display for DBusCall {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"dbus {} {} {}.{}",
+			self.destination, self.path, self.interface, self.member
+		)?;
+		for arg in &self.args {
+			write!(f, "\n{arg}")?;
+		}
+		Ok(())
+	}
+}
+
+/// The operations, witch would be performed to set the wallpapers.
+/// Created by [`WallpaperBuilder::plan()`].
+#[derive(Debug)]
+pub struct Plan {
+	operations: Vec<Operation>,
+	pub(crate) builder: WallpaperBuilder,
+}
+
+impl Plan {
+	/// Return the planned operations.
+	pub fn operations(&self) -> &[Operation] {
+		&self.operations
+	}
+
+	/// Execute the plan.
+	/// If the desktop has changed since the plan was created, the executed operations can differ from the planned ones.
+	pub fn apply(self) -> Result<(), WallpaperError> {
+		self.builder.apply_changes()
+	}
+}
+
+impl fmt::Display for Plan {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for (i, operation) in self.operations.iter().enumerate() {
+			if i != 0 {
+				writeln!(f)?;
+			}
+			write!(f, "{operation}")?;
+		}
+		Ok(())
+	}
+}
+
+thread_local! {
+	static OPERATIONS: RefCell<Option<Vec<Operation>>> = const { RefCell::new(None) };
+}
+
+/// restore the previous recording, even if planning panics
+struct Reset(Option<Vec<Operation>>);
+
+impl Drop for Reset {
+	fn drop(&mut self) {
+		OPERATIONS.with(|operations| operations.replace(self.0.take()));
+	}
+}
+
+/// create a plan from the changes of the builder
+pub(crate) fn plan(builder: WallpaperBuilder) -> Result<Plan, WallpaperError> {
+	let (operations, result) = recording(|| builder.clone().apply_changes());
+	result?;
+	Ok(Plan { operations, builder })
+}
+
+/// Execute `f` as plan and return the recorded operations.
+/// The operations are not added to a plan, witch is created at the same time.
+pub(crate) fn recording<F, T>(f: F) -> (Vec<Operation>, T)
+where
+	F: FnOnce() -> T,
+{
+	let _reset = Reset(OPERATIONS.with(|operations| operations.replace(Some(Vec::new()))));
+	let result = f();
+	let operations = OPERATIONS.with(|operations| operations.replace(None)).unwrap_or_default();
+	(operations, result)
+}
+
+/// Record the operation, if a plan is created.
+/// Return `true`, if the operation was recorded and must not be executed.
+pub(crate) fn record<F>(operation: F) -> bool
+where
+	F: FnOnce() -> Operation,
+{
+	OPERATIONS.with(|operations| match operations.borrow_mut().as_mut() {
+		Some(operations) => {
+			operations.push(operation());
+			true
+		},
+		None => false,
+	})
+}
+
+/// check if a plan is created
+pub(crate) fn is_planning() -> bool {
+	OPERATIONS.with(|operations| operations.borrow().is_some())
+}
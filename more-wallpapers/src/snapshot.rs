@@ -0,0 +1,136 @@
+use crate::{BuilderOptions, Environment, Screen, WallpaperBuilder, WallpaperError};
+use serde::{Deserialize, Serialize};
+
+/// Serializable state of all screens, including inactive screens.
+/// Can be used to restore the wallpapers later, for example to revert a preview.
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use more_wallpapers::{Mode, WallpaperBuilder, WallpaperSnapshot};
+///
+/// let snapshot = WallpaperSnapshot::capture()?;
+/// WallpaperBuilder::new()?.set_wallpapers(|_| ("preview.jpg", Mode::Crop))?;
+/// // user does not like the preview
+/// snapshot.restore()?;
+/// # Ok(())}
+/// ```
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WallpaperSnapshot {
+	pub environment: Environment,
+	pub screens: Vec<Screen>,
+}
+
+impl WallpaperSnapshot {
+	/// Capture the current state of all screens.
+	pub fn capture() -> Result<Self, WallpaperError> {
+		Ok(WallpaperBuilder::new()?.snapshot())
+	}
+
+	/// Restore the wallpapers of the snapshot.
+	/// Screens, witch does not exist anymore or whose wallpaper was unknown at capture time, are skipped.
+	///
+	/// Return [`WallpaperError::EnvironmentMismatch`], if the snapshot was captured at a different environment.
+	pub fn restore(self) -> Result<(), WallpaperError> {
+		self.apply(WallpaperBuilder::options(), false)
+	}
+
+	/// Like [`Self::restore()`], but also restore the snapshot if it was captured at a different environment.
+	/// Only screens with the same name as at capture time are restored.
+	pub fn restore_forced(self) -> Result<(), WallpaperError> {
+		self.apply(WallpaperBuilder::options(), true)
+	}
+
+	pub(crate) fn apply(self, options: BuilderOptions, force: bool) -> Result<(), WallpaperError> {
+		let mut builder = options.build()?;
+		self.restore_into(&mut builder, force)?;
+		builder.apply_changes()
+	}
+
+	/// mark the screens of the snapshot as changed at the `builder`
+	fn restore_into(self, builder: &mut WallpaperBuilder, force: bool) -> Result<(), WallpaperError> {
+		if builder.environment != self.environment && !force {
+			return Err(WallpaperError::EnvironmentMismatch {
+				snapshot: self.environment,
+				current: builder.environment,
+			});
+		}
+		for snapshot_screen in self.screens.into_iter().filter(Screen::is_set) {
+			let Some(i) = builder.screens.iter().position(|screen| screen.name == snapshot_screen.name) else {
+				continue;
+			};
+			let screen = &mut builder.screens[i];
+			screen.background = snapshot_screen.background;
+			screen.mode = snapshot_screen.mode;
+			screen.fill_color = snapshot_screen.fill_color;
+			screen.focal_point = snapshot_screen.focal_point;
+			builder.changed[i] = true;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+	use super::*;
+	use crate::{Background, Color, FocalPoint, Mode, Session};
+
+	fn screen(name: &str) -> Screen {
+		Screen {
+			name: name.to_owned(),
+			background: None,
+			mode: None,
+			fill_color: None,
+			focal_point: None,
+			active: true,
+			primary: false,
+			geometry: None,
+		}
+	}
+
+	fn snapshot() -> WallpaperSnapshot {
+		let mut image = screen("DP-1");
+		image.background = Some(Background::Image("/wallpaper.jpg".into()));
+		image.mode = Some(Mode::Fit);
+		image.fill_color = Some(Color::new(0, 43, 54));
+		image.focal_point = Some(FocalPoint::new(0.25, 0.75));
+		let mut color = screen("DP-2");
+		color.background = Some(Background::Color(Color::WHITE));
+		color.mode = Some(Mode::Stretch);
+		WallpaperSnapshot {
+			environment: Environment::Sway,
+			screens: vec![image, color, screen("HDMI-1")],
+		}
+	}
+
+	#[test]
+	fn snapshot_round_trip() {
+		let snapshot = snapshot();
+		let json = serde_json::to_string(&snapshot).unwrap();
+		let restored: WallpaperSnapshot = serde_json::from_str(&json).unwrap();
+		assert_eq!(restored.environment, snapshot.environment);
+		assert_eq!(
+			serde_json::to_value(&restored.screens).unwrap(),
+			serde_json::to_value(&snapshot.screens).unwrap()
+		);
+
+		// screens, witch are unknown or whose wallpaper was unknown, are skipped
+		let screens = vec![screen("DP-2"), screen("DP-1"), screen("HDMI-1"), screen("DP-3")];
+		let mut builder = WallpaperBuilder::from_screens(Environment::Sway, screens, Session::default());
+		restored.restore_into(&mut builder, false).unwrap();
+		assert_eq!(builder.changed, [true, true, false, false]);
+		assert_eq!(builder.screens[0].background, Some(Background::Color(Color::WHITE)));
+		let screen = &builder.screens[1];
+		assert_eq!(screen.wallpaper(), Some("/wallpaper.jpg".into()));
+		assert_eq!(screen.mode, Some(Mode::Fit));
+		assert_eq!(screen.fill_color, Some(Color::new(0, 43, 54)));
+		assert_eq!(screen.focal_point, Some(FocalPoint::new(0.25, 0.75)));
+	}
+
+	#[test]
+	fn snapshot_of_other_environment() {
+		let mut builder = WallpaperBuilder::from_screens(Environment::Kde, vec![screen("DP-1")], Session::default());
+		let result = snapshot().restore_into(&mut builder, false);
+		assert!(matches!(result, Err(WallpaperError::EnvironmentMismatch { .. })));
+		snapshot().restore_into(&mut builder, true).unwrap();
+		assert_eq!(builder.changed, [true]);
+	}
+}
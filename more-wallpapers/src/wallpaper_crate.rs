@@ -0,0 +1,120 @@
+//! Backend, witch uses the [fallback crate](https://crates.io/crates/wallpaper).
+//! It is the built-in backend of macOS and Windows and the fallback at linux.
+
+use crate::{backend::Session, plan, Background, Operation, Screen, WallpaperError};
+#[cfg(target_os = "linux")]
+use crate::{linux::render, Mode};
+#[cfg(not(target_os = "linux"))]
+pub(crate) use platform::*;
+
+pub(crate) fn get_screens(_session: &Session) -> Result<Vec<Screen>, WallpaperError> {
+	Ok(vec![Screen {
+		name: "Unknow".into(),
+		background: fallback::get().ok().map(|wallpaper| Background::Image(wallpaper.into())),
+		mode: None,
+		fill_color: None,
+		focal_point: None,
+		active: true,
+		primary: true,
+		geometry: None,
+	}])
+}
+
+/// set the wallpaper of the first changed screen, because the fallback crate does only support one wallpaper
+pub(crate) fn set_screens(session: &Session, screens: Vec<Screen>, changed: &[bool]) -> Result<(), WallpaperError> {
+	let Some((screen, _)) = screens.iter().zip(changed).find(|(_, changed)| **changed) else {
+		return Ok(());
+	};
+	let background = screen.require_background()?;
+	#[cfg(target_os = "linux")]
+	let (wallpaper, mode) = (
+		render::background_image(session, background)?,
+		// colors and gradients are rendered into an image, witch must fill the screen
+		match background {
+			Background::Image(_) => screen.require_mode()?,
+			_ => Mode::Stretch,
+		},
+	);
+	#[cfg(not(target_os = "linux"))]
+	let (wallpaper, mode) = (
+		background.image().ok_or(WallpaperError::UnsupportedBackground)?,
+		screen.require_mode()?,
+	);
+	let recorded = plan::record(|| Operation::Fallback {
+		wallpaper: wallpaper.to_string(),
+		mode,
+	});
+	if recorded {
+		return Ok(());
+	}
+	// the fallback crate can not be interrupted, so the token is only checked before
+	session.check_cancelled()?;
+	fallback::set_from_path(wallpaper.as_str())?;
+	fallback::set_mode(mode.into())?;
+	Ok(())
+}
+
+/// glue code of macOS and Windows, witch do only support this backend
+#[cfg(not(target_os = "linux"))]
+mod platform {
+	use crate::{
+		backend::{self, BuiltinBackend, Session},
+		Environment, MissingRequirement, Screen, WallpaperBuilder, WallpaperError,
+	};
+
+	#[cfg(target_os = "macos")]
+	const ENVIRONMENT: Environment = Environment::MacOS;
+	#[cfg(target_os = "windows")]
+	const ENVIRONMENT: Environment = Environment::Windows;
+
+	#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", ret))]
+	pub(crate) fn get_environment() -> Result<Environment, WallpaperError> {
+		// the built-in environment is always available, so registered backends with a negative priority are never used
+		Ok(backend::detect(true)
+			.map(|backend| Environment::Custom(backend.name()))
+			.unwrap_or(ENVIRONMENT))
+	}
+
+	/// return the built-in environment, witch can be used at the current session
+	pub(crate) fn detect_builtin() -> Option<Environment> {
+		Some(ENVIRONMENT)
+	}
+
+	pub(crate) fn builtin_backend(environment: Environment) -> Option<BuiltinBackend> {
+		(environment == ENVIRONMENT).then_some(BuiltinBackend {
+			environment,
+			get_screens: super::get_screens,
+			set_screens: super::set_screens,
+		})
+	}
+
+	#[cfg_attr(
+		feature = "tracing",
+		tracing::instrument(level = "debug", name = "get_screens", skip_all, fields(environment = %environment))
+	)]
+	pub(crate) fn get_builder(environment: Environment, session: Session) -> Result<WallpaperBuilder, WallpaperError> {
+		let screens = backend::backend(environment)?.get_screens(&session)?;
+		Ok(WallpaperBuilder::from_screens(environment, screens, session))
+	}
+
+	pub(crate) fn missing_requirements(_environment: Environment) -> Vec<MissingRequirement> {
+		// the fallback crate uses system apis, witch are always available
+		Vec::new()
+	}
+
+	#[cfg_attr(
+		feature = "tracing",
+		tracing::instrument(level = "debug", name = "set_screens", skip_all, fields(environment = %builder.environment))
+	)]
+	pub(crate) fn set_screens_from_builder(builder: WallpaperBuilder) -> Result<(), WallpaperError> {
+		let backend = backend::backend(builder.environment)?;
+		// screens with unknown state can not be passed to the backends
+		let (screens, changed): (Vec<Screen>, Vec<bool>) = builder
+			.screens
+			.into_iter()
+			.zip(builder.changed)
+			.filter(|(screen, _)| screen.is_set())
+			.unzip();
+		backend::set_screens(backend.as_ref(), &builder.session, screens, &changed)
+	}
+}
@@ -1,10 +1,13 @@
-use clap::Parser;
-use more_wallpapers::{Mode, WallpaperBuilder};
+use clap::{Parser, Subcommand};
+use more_wallpapers::{Environment, Mode, WallpaperBuilder};
 
 #[derive(Debug, Parser)]
 pub struct SetVecOpt {
 	#[clap(required = true)]
 	images: Vec<String>,
+	/// print the operations, witch would be performed, instead of setting the wallpapers
+	#[clap(long)]
+	dry_run: bool,
 }
 
 #[derive(Debug, Parser)]
@@ -18,6 +21,15 @@ pub struct SetOpt {
 }
 
 #[derive(Debug, Parser)]
+struct Args {
+	/// use the given environment, instead of detecting it
+	#[clap(short, long, global = true)]
+	environment: Option<Environment>,
+	#[clap(subcommand)]
+	command: Opt,
+}
+
+#[derive(Debug, Subcommand)]
 enum Opt {
 	/// list avaible screens and other information
 	List,
@@ -28,13 +40,36 @@ enum Opt {
 	Set(SetOpt),
 	*/
 }
-fn list() -> anyhow::Result<()> {
-	let builder = WallpaperBuilder::new()?;
+fn builder(environment: Option<Environment>) -> anyhow::Result<WallpaperBuilder> {
+	Ok(match environment {
+		Some(environment) => WallpaperBuilder::with_environment(environment)?,
+		None => WallpaperBuilder::new()?,
+	})
+}
+
+fn list(environment: Option<Environment>) -> anyhow::Result<()> {
+	// a missing dependency is often the reason, why the builder can not be created,
+	// so the dependencies are listed first
+	let missing = match environment {
+		Some(environment) => environment.check_requirements(),
+		None => more_wallpapers::check_requirements()?,
+	};
+	for missing in missing {
+		println!("missing dependency: {missing}");
+	}
+	let builder = builder(environment)?;
 	println!("environment: {}", builder.environment());
-	println!(
-		"support various wallpaper: {}",
-		builder.environment().support_various_wallpaper()
-	);
+	let capabilities = builder.environment().capabilities();
+	println!("support various wallpaper: {}", capabilities.various_wallpaper);
+	print!("supported modes:");
+	for mode in capabilities.modes {
+		print!(" {mode}");
+	}
+	println!();
+	println!("read current wallpaper: {}", capabilities.read_wallpaper);
+	println!("persistent after restart: {}", capabilities.persistent);
+	println!("set lock screen: {}", capabilities.lock_screen);
+	println!("wallpaper per workspace: {}", capabilities.per_workspace);
 	print!("active screens:");
 	for screen in builder.screens() {
 		if screen.active {
@@ -68,18 +103,26 @@ fn set(opt: SetOpt) -> anyhow::Result<()> {
 }
 */
 
-fn set_vec(opt: SetVecOpt) -> anyhow::Result<()> {
+fn set_vec(opt: SetVecOpt, environment: Option<Environment>) -> anyhow::Result<()> {
 	let default = opt.images.first().unwrap().to_owned();
-	let used_wallpapers = more_wallpapers::set_wallpapers_from_vec(opt.images, default, Mode::Crop)?;
+	if opt.dry_run {
+		let mut builder = builder(environment)?;
+		let used_wallpapers = builder.select_wallpapers_from_vec(opt.images, default, Mode::Crop);
+		println!("{}", builder.plan()?);
+		println!("The backgrounds would be set to the following wallpapers {used_wallpapers:?}");
+		return Ok(());
+	}
+	let used_wallpapers = builder(environment)?.set_wallpapers_from_vec(opt.images, default, Mode::Crop)?;
 	println!("The backgrounds have been set to the following wallpapers {used_wallpapers:?}");
 	Ok(())
 }
 
 fn main() {
-	let result = match Opt::parse() {
-		Opt::List => list(),
+	let args = Args::parse();
+	let result = match args.command {
+		Opt::List => list(args.environment),
 		//Opt::Set(opt) => set(opt),
-		Opt::SetVec(opt) => set_vec(opt),
+		Opt::SetVec(opt) => set_vec(opt, args.environment),
 	};
 	if let Err(err) = result {
 		eprintln!("ERROR: {err}");